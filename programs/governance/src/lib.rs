@@ -0,0 +1,436 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+
+declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnG");
+/// Seed of the single governance account every program reads the governor from
+pub const GOVERNANCE_SEED: &[u8] = b"governance";
+/// Largest multisig the governance account has room for
+pub const MAX_MEMBERS: usize = 10;
+/// Seed of the PDA that signs queued changes once their timelock expires
+pub const TIMELOCK_AUTHORITY_SEED: &[u8] = b"timelock_authority";
+/// Seed of a queued change, followed by its id
+pub const TIMELOCKED_CHANGE_SEED: &[u8] = b"timelocked_change";
+/// Shortest notice, in seconds, governance may give before a queued change executes
+pub const MIN_TIMELOCK_DELAY: i64 = 2 * 24 * 60 * 60;
+/// Seed of the single pause registry every program checks before acting
+pub const PAUSE_REGISTRY_SEED: &[u8] = b"pause_registry";
+/// Most markets the pause registry can hold flags for
+pub const MAX_PAUSED_MARKETS: usize = 32;
+/// Operation classes that can be paused, globally or per market
+pub const PAUSE_OPENING: u8 = 1 << 0;
+pub const PAUSE_CLOSING: u8 = 1 << 1;
+pub const PAUSE_LIQUIDITY: u8 = 1 << 2;
+pub const PAUSE_LIQUIDATION: u8 = 1 << 3;
+pub const PAUSE_ORDERS: u8 = 1 << 4;
+
+#[program]
+pub mod governance {
+    use super::*;
+
+    // Creates the governance account, can only run once
+    pub fn initialize(ctx: Context<Initialize>, governor: Pubkey) -> Result<()> {
+        let governance: &mut Account<'_, Governance> = &mut ctx.accounts.governance;
+        governance.governor = governor;
+        governance.pending_governor = Pubkey::default();
+        governance.members = Vec::new();
+        governance.threshold = 0;
+        governance.change_count = 0;
+        emit!(GovernorChanged{ previous_governor : Pubkey::default() , new_governor : governor });
+        Ok(())
+    }
+
+    // First step of the transfer, `new_governor` must accept before it takes over
+    pub fn propose_governor(ctx: Context<GovernorAction>, new_governor: Pubkey) -> Result<()> {
+        require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let governance: &mut Account<'_, Governance> = &mut ctx.accounts.governance;
+        governance.pending_governor = new_governor;
+        emit!(GovernorProposed{ governor : governance.governor , pending_governor : new_governor });
+        Ok(())
+    }
+
+    // Second step of the transfer, signed by the proposed governor
+    pub fn accept_governor(ctx: Context<AcceptGovernor>) -> Result<()> {
+        let governance: &mut Account<'_, Governance> = &mut ctx.accounts.governance;
+        require!(governance.pending_governor != Pubkey::default() , MyError::NoPendingGovernor);
+        require!(governance.pending_governor == ctx.accounts.pending_governor.key() , MyError::CallerUnauthorized);
+        let previous_governor: Pubkey = governance.governor;
+        governance.governor = governance.pending_governor;
+        governance.pending_governor = Pubkey::default();
+        emit!(GovernorChanged{ previous_governor , new_governor : governance.governor });
+        Ok(())
+    }
+
+    // Requires `threshold` of `members` to co-sign every governor action, a threshold of 0 disables the multisig
+    pub fn set_multisig(ctx: Context<GovernorAction>, members: Vec<Pubkey>, threshold: u8) -> Result<()> {
+        require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        require!(members.len() <= MAX_MEMBERS , MyError::TooManyMembers);
+        require!(usize::from(threshold) <= members.len() , MyError::InvalidThreshold);
+        let governance: &mut Account<'_, Governance> = &mut ctx.accounts.governance;
+        governance.members = members;
+        governance.threshold = threshold;
+        emit!(MultisigUpdated{ members : governance.members.clone() , threshold });
+        Ok(())
+    }
+
+    // Creates the pause registry with nothing paused
+    pub fn initialize_pause_registry(ctx: Context<InitializePauseRegistry>, guardian: Pubkey) -> Result<()> {
+        require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let registry: &mut Account<'_, PauseRegistry> = &mut ctx.accounts.pause_registry;
+        registry.guardian = guardian;
+        registry.global = 0;
+        registry.markets = Vec::new();
+        emit!(GuardianChanged{ guardian });
+        Ok(())
+    }
+
+    pub fn set_guardian(ctx: Context<PauseAction>, guardian: Pubkey) -> Result<()> {
+        require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        ctx.accounts.pause_registry.guardian = guardian;
+        emit!(GuardianChanged{ guardian });
+        Ok(())
+    }
+
+    // Pauses `operations` for `market`, or everywhere when `market` is None. The guardian can do so without governance.
+    pub fn pause(ctx: Context<PauseAction>, market: Option<Pubkey>, operations: u8) -> Result<()> {
+        if ctx.accounts.authorized_account.key() != ctx.accounts.pause_registry.guardian {
+            require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        }
+        let registry: &mut Account<'_, PauseRegistry> = &mut ctx.accounts.pause_registry;
+        match market {
+            None => registry.global |= operations,
+            Some(market) => match registry.markets.iter_mut().find(|m| m.market == market) {
+                Some(flags) => flags.paused |= operations,
+                None => {
+                    require!(registry.markets.len() < MAX_PAUSED_MARKETS , MyError::TooManyPausedMarkets);
+                    registry.markets.push(MarketPause{ market , paused : operations });
+                }
+            },
+        }
+        emit!(Paused{ market , operations , by : ctx.accounts.authorized_account.key() });
+        Ok(())
+    }
+
+    // Lifts the pause of `operations` for `market`, or of the global flags when `market` is None. Governor only.
+    pub fn unpause(ctx: Context<PauseAction>, market: Option<Pubkey>, operations: u8) -> Result<()> {
+        require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let registry: &mut Account<'_, PauseRegistry> = &mut ctx.accounts.pause_registry;
+        match market {
+            None => registry.global &= !operations,
+            Some(market) => {
+                if let Some(flags) = registry.markets.iter_mut().find(|m| m.market == market) {
+                    flags.paused &= !operations;
+                }
+                registry.markets.retain(|m| m.paused != 0);
+            }
+        }
+        emit!(Unpaused{ market , operations });
+        Ok(())
+    }
+
+    // Queues `data` to be sent to `program_id` with `accounts` no earlier than `eta`
+    pub fn schedule_change(ctx: Context<ScheduleChange>, program_id: Pubkey, accounts: Vec<TimelockedAccount>, data: Vec<u8>, eta: i64) -> Result<()> {
+        require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let now: i64 = Clock::get()?.unix_timestamp;
+        require!(eta >= now + MIN_TIMELOCK_DELAY , MyError::EtaTooSoon);
+
+        let governance: &mut Account<'_, Governance> = &mut ctx.accounts.governance;
+        let id: u64 = governance.change_count;
+        governance.change_count += 1;
+        let change: &mut Account<'_, TimelockedChange> = &mut ctx.accounts.change;
+        change.id = id;
+        change.program_id = program_id;
+        change.accounts = accounts;
+        change.data = data;
+        change.eta = eta;
+        change.executed = false;
+        emit!(ChangeQueued{
+            id,
+            program_id,
+            data : change.data.clone(),
+            eta,
+        });
+        Ok(())
+    }
+
+    // Drops a queued change before it executes, refunding its rent to the governor
+    pub fn cancel_change(ctx: Context<CancelChange>) -> Result<()> {
+        require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        require!(!ctx.accounts.change.executed , MyError::ChangeAlreadyExecuted);
+        emit!(ChangeCanceled{ id : ctx.accounts.change.id });
+        Ok(())
+    }
+
+    // Sends a queued change once its eta has passed, callable by anyone.
+    // The remaining accounts are the target program followed by the accounts of the change.
+    pub fn execute_change<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteChange<'info>>) -> Result<()> {
+        let change: &mut Account<'_, TimelockedChange> = &mut ctx.accounts.change;
+        require!(!change.executed , MyError::ChangeAlreadyExecuted);
+        require!(Clock::get()?.unix_timestamp >= change.eta , MyError::ChangeNotReady);
+
+        let timelock_authority: Pubkey = ctx.accounts.timelock_authority.key();
+        let accounts: Vec<AccountMeta> = change.accounts.iter().map(|account| AccountMeta{
+            pubkey : account.pubkey,
+            is_signer : account.is_signer || account.pubkey == timelock_authority,
+            is_writable : account.is_writable,
+        }).collect();
+        let instruction: Instruction = Instruction{
+            program_id : change.program_id,
+            accounts,
+            data : change.data.clone(),
+        };
+        let mut account_infos: Vec<AccountInfo> = ctx.remaining_accounts.to_vec();
+        account_infos.push(ctx.accounts.timelock_authority.to_account_info());
+        let bump: u8 = ctx.bumps.timelock_authority;
+        invoke_signed(&instruction, &account_infos, &[&[TIMELOCK_AUTHORITY_SEED, &[bump]]])?;
+
+        change.executed = true;
+        emit!(ChangeExecuted{ id : change.id , program_id : change.program_id });
+        Ok(())
+    }
+}
+
+/// Whether any of `operations` is paused globally or for `market`.
+pub fn is_paused(registry: &PauseRegistry, market: Pubkey, operations: u8) -> bool {
+    registry.global & operations != 0
+        || registry.markets.iter().any(|m| m.market == market && m.paused & operations != 0)
+}
+
+/// Errors if any of `operations` is paused globally or for `market`.
+/// Callers that don't know the market pass `Pubkey::default()`, which only checks the global flags.
+pub fn require_not_paused(registry: &PauseRegistry, market: Pubkey, operations: u8) -> Result<()> {
+    require!(!is_paused(registry, market, operations) , MyError::OperationPaused);
+    Ok(())
+}
+
+/// Errors unless `authorized_account` is the signing governor and, when a multisig is set,
+/// at least `threshold` distinct members also signed among `co_signers`.
+pub fn require_governor(governance: &Governance, authorized_account: &AccountInfo, co_signers: &[AccountInfo]) -> Result<()> {
+    require!(authorized_account.is_signer && authorized_account.key() == governance.governor , MyError::CallerUnauthorized);
+    if governance.threshold == 0 {
+        return Ok(());
+    }
+    let mut signed: Vec<Pubkey> = Vec::new();
+    for account in co_signers.iter() {
+        if account.is_signer && governance.members.contains(account.key) && !signed.contains(account.key) {
+            signed.push(account.key());
+        }
+    }
+    require!(signed.len() >= usize::from(governance.threshold) , MyError::ThresholdNotMet);
+    Ok(())
+}
+
+#[account]
+pub struct Governance {
+    pub governor : Pubkey,
+    // Pubkey::default() when no transfer is in progress
+    pub pending_governor : Pubkey,
+    pub members : Vec<Pubkey>,
+    pub threshold : u8,
+    pub change_count : u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct MarketPause {
+    pub market : Pubkey,
+    pub paused : u8,
+}
+
+#[account]
+pub struct PauseRegistry {
+    pub guardian : Pubkey,
+    pub global : u8,
+    pub markets : Vec<MarketPause>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct TimelockedAccount {
+    pub pubkey : Pubkey,
+    pub is_signer : bool,
+    pub is_writable : bool,
+}
+
+#[account]
+pub struct TimelockedChange {
+    pub id : u64,
+    pub program_id : Pubkey,
+    pub accounts : Vec<TimelockedAccount>,
+    pub data : Vec<u8>,
+    pub eta : i64,
+    pub executed : bool,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init, payer = payer, space = 8 + 32 + 32 + 4 + 32 * MAX_MEMBERS + 1 + 8, seeds = [GOVERNANCE_SEED], bump)]
+    pub governance: Account<'info, Governance>,
+    // only the program's upgrade authority can set the first governor
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ MyError::CallerUnauthorized)]
+    pub program: Program<'info, crate::program::Governance>,
+    #[account(constraint = program_data.upgrade_authority_address == Some(payer.key()) @ MyError::CallerUnauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GovernorAction<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut, seeds = [GOVERNANCE_SEED], bump)]
+    pub governance: Account<'info, Governance>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptGovernor<'info> {
+    pub pending_governor: Signer<'info>,
+    #[account(mut, seeds = [GOVERNANCE_SEED], bump)]
+    pub governance: Account<'info, Governance>,
+}
+
+#[derive(Accounts)]
+pub struct InitializePauseRegistry<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump)]
+    pub governance: Account<'info, Governance>,
+    #[account(init, payer = payer, space = 8 + 32 + 1 + 4 + 33 * MAX_PAUSED_MARKETS, seeds = [PAUSE_REGISTRY_SEED], bump)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PauseAction<'info> {
+    /// CHECK: the guardian, or checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump)]
+    pub governance: Account<'info, Governance>,
+    #[account(mut, seeds = [PAUSE_REGISTRY_SEED], bump)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+#[instruction(program_id: Pubkey, accounts: Vec<TimelockedAccount>, data: Vec<u8>)]
+pub struct ScheduleChange<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut, seeds = [GOVERNANCE_SEED], bump)]
+    pub governance: Account<'info, Governance>,
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + 8 + 32 + 4 + 34 * accounts.len() + 4 + data.len() + 8 + 1,
+        seeds = [TIMELOCKED_CHANGE_SEED, &governance.change_count.to_le_bytes()],
+        bump,
+    )]
+    pub change: Account<'info, TimelockedChange>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelChange<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer, mut)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump)]
+    pub governance: Account<'info, Governance>,
+    #[account(mut, close = authorized_account, seeds = [TIMELOCKED_CHANGE_SEED, &change.id.to_le_bytes()], bump)]
+    pub change: Account<'info, TimelockedChange>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteChange<'info> {
+    #[account(mut, seeds = [TIMELOCKED_CHANGE_SEED, &change.id.to_le_bytes()], bump)]
+    pub change: Account<'info, TimelockedChange>,
+    /// CHECK: PDA signing the change towards the target program
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump)]
+    pub timelock_authority: AccountInfo<'info>,
+}
+
+#[event]
+pub struct GuardianChanged {
+    pub guardian : Pubkey,
+}
+
+#[event]
+pub struct Paused {
+    pub market : Option<Pubkey>,
+    pub operations : u8,
+    pub by : Pubkey,
+}
+
+#[event]
+pub struct Unpaused {
+    pub market : Option<Pubkey>,
+    pub operations : u8,
+}
+
+#[event]
+pub struct ChangeQueued {
+    pub id : u64,
+    pub program_id : Pubkey,
+    pub data : Vec<u8>,
+    pub eta : i64,
+}
+
+#[event]
+pub struct ChangeCanceled {
+    pub id : u64,
+}
+
+#[event]
+pub struct ChangeExecuted {
+    pub id : u64,
+    pub program_id : Pubkey,
+}
+
+#[event]
+pub struct GovernorProposed {
+    pub governor : Pubkey,
+    pub pending_governor : Pubkey,
+}
+
+#[event]
+pub struct GovernorChanged {
+    pub previous_governor : Pubkey,
+    pub new_governor : Pubkey,
+}
+
+#[event]
+pub struct MultisigUpdated {
+    pub members : Vec<Pubkey>,
+    pub threshold : u8,
+}
+
+#[error_code]
+pub enum MyError {
+    #[msg("Unauthorsized caller")]
+    CallerUnauthorized,
+    #[msg("No pending governor")]
+    NoPendingGovernor,
+    #[msg("Too many multisig members")]
+    TooManyMembers,
+    #[msg("Invalid multisig threshold")]
+    InvalidThreshold,
+    #[msg("Multisig threshold not met")]
+    ThresholdNotMet,
+    #[msg("Eta is earlier than the timelock delay allows")]
+    EtaTooSoon,
+    #[msg("Change is not ready to execute")]
+    ChangeNotReady,
+    #[msg("Change already executed")]
+    ChangeAlreadyExecuted,
+    #[msg("Too many paused markets")]
+    TooManyPausedMarkets,
+    #[msg("Operation paused")]
+    OperationPaused,
+}
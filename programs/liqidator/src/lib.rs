@@ -1,19 +1,21 @@
 use anchor_lang::prelude::*;
-use anchor_lang::InstructionData;
-use anchor_lang::solana_program;
-use anchor_lang::solana_program::program::invoke;
-use router::cpi::accounts::PositionManagement;
+use governance::{Governance, PauseRegistry, GOVERNANCE_SEED, PAUSE_REGISTRY_SEED, PAUSE_LIQUIDATION, TIMELOCK_AUTHORITY_SEED};
+use router::cpi::accounts::ClosePositionByLiquidator;
 use router::program::Router;
 use router::{self , ContractState};
+use anchor_spl::token::{Token, TokenAccount};
+use liquidity_position_util::position_util::{self, LiquidityPosition, MaintainMarginRateParameter, Position, State as PoolState};
+use liquidity_position_util::program::Pool;
+use liquidity_position_util::LIQUIDATOR_AUTHORITY_SEED;
+use price_feed::{PriceFeed, TokenPrice};
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnR");
-const GOVERNOR_PUBKEY: Pubkey = Pubkey::new_from_array([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32]);
 #[program]
 pub mod liquidator {
     use super::*;
 
      
     pub fn initialize(ctx: Context<Initialize> , _router : Pubkey , _pool_factory : Pubkey , _efc:Pubkey) -> Result<()> {
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         require!(!state.initilized , MyError::AlreadyInitlized );
         state.initilized = true;
@@ -25,7 +27,6 @@ pub mod liquidator {
 
     // Function to update price feed
     pub fn update_price_feed(ctx: Context<UpdatePriceFeed> , _price_feed : Pubkey) -> Result<()> {
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
         // Logic to update price feed
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         state.price_feed= _price_feed;
@@ -35,7 +36,7 @@ pub mod liquidator {
 
     pub fn add_executor(ctx: Context<GovernanceAction>, new_executor: Pubkey) -> Result<()> {
         // Ensure the caller is the governor
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
 
         // Add new executor to the list
         let governance_state: &mut Account<'_, State> = &mut ctx.accounts.governance_state;
@@ -45,7 +46,7 @@ pub mod liquidator {
 
     // Function to update executor
     pub fn remove_executor(ctx: Context<UpdateExecutor>, executor: Pubkey) -> Result<()> {
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
         let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         address_list.retain(|&x| x != executor);
         // Logic to update executor
@@ -57,73 +58,144 @@ pub mod liquidator {
         let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         let user_pubkey: Pubkey = ctx.accounts.user.key();
         require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidOperation);
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDATION)?;
+
+        let pool_state: &PoolState = &ctx.accounts.pool_state;
+        let index: usize = position_util::get_liquidity_position_index(pool_state, _position_id).ok_or(MyError::PositionNotFound)?;
+        _require_liquidity_position_liquidatable(pool_state, &pool_state.liquidity_positions[index])?;
+        // the pool rebuilds its price vertices from the remaining liquidity at the max price
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, pool_state.token, true, ctx.remaining_accounts)?;
+
+        let bump: u8 = ctx.bumps.liquidator_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[LIQUIDATOR_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::LiquidatePosition{
+            liquidator_authority : ctx.accounts.liquidator_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            fee_receiver_token : ctx.accounts.fee_receiver_token.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds);
+        liquidity_position_util::cpi::liquidate_liquidity_position(cpi_ctx , _position_id , _fee_reciever , index_price_x96)
+    }
 
-        Ok(())
+    // Function to auto-deleverage the top ranked position, the `account`'s on `side`, while the pool's liquidation fund is in deficit
+    pub fn adl_position(ctx: Context<AdlPosition>, account : Pubkey , side : bool , pool : Pubkey) -> Result<()> {
+        let address_list: &Vec<Pubkey> = &ctx.accounts.state.executors;
+        require!(address_list.contains(&ctx.accounts.user.key()) , MyError::CallerUnauthorized);
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidOperation);
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDATION)?;
+
+        let mark_price_x96: u128 = _choose_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.pool_state.token, side, ctx.remaining_accounts)?;
+        let bump: u8 = ctx.bumps.liquidator_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[LIQUIDATOR_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::AutoDeleveragePosition{
+            liquidator_authority : ctx.accounts.liquidator_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            account_token : ctx.accounts.account_token.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds);
+        liquidity_position_util::cpi::adl_position(cpi_ctx , account , side , mark_price_x96)
     }
 
     // Function to liquidate position
-    pub fn liquidate_position(ctx: Context<LiquidatePosition>, token : Pubkey , account : Pubkey , side : bool , _fee_reciever : Pubkey  , pool : Pubkey ) -> Result<()> {
-        // Logic to liquidate position
-        let addresses: AccountInfo<'_> = ctx.accounts.authorized_account.clone();
-        
+    pub fn liquidate_position<'info>(ctx: Context<'_, '_, 'info, 'info, LiquidatePosition<'info>>, account : Pubkey , side : bool , _fee_reciever : Pubkey  , pool : Pubkey ) -> Result<()> {
         let user_pubkey: &Signer<'_> = &ctx.accounts.user;
         let address_list: &Vec<Pubkey> = &ctx.accounts.state.executors;
-        
         require!(address_list.contains(&user_pubkey.key()) , MyError::CallerUnauthorized);
-        // let decrease_index_price = _choose_index_price(ctx ,token , size)?;
-        let _has_unrealized_profit:bool = false;
-        let size: u128 = 100;
-        if size ==0 ||  _has_unrealized_profit {
-            // liquidate position 
-           return Ok(())
+        require!(ctx.accounts.authorized_account.key() == account , MyError::InvalidOperation);
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidOperation);
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDATION)?;
+
+        let pool_state: &PoolState = &ctx.accounts.pool_state;
+        let index: usize = position_util::get_position_index(pool_state, account, side).ok_or(MyError::PositionNotFound)?;
+        let position: Position = pool_state.positions[index].clone();
+        require!(position.size > 0 , MyError::PositionNotFound);
+
+        let decrease_index_price_x96: u128 = _choose_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, pool_state.token, side, ctx.remaining_accounts)?;
+        _require_liquidatable(pool_state, side, &position, decrease_index_price_x96)?;
+
+        let bump: u8 = ctx.bumps.liquidator_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[LIQUIDATOR_AUTHORITY_SEED, &[bump]]];
+        // a position still in profit is closed through the router so the owner keeps the remaining margin
+        if _has_unrealized_profit(position.entry_price_x96, side, decrease_index_price_x96) {
+            let cpi_accounts: ClosePositionByLiquidator<'info> = ClosePositionByLiquidator{
+                state : ctx.accounts.router_state.to_account_info(),
+                user : ctx.accounts.liquidator_authority.to_account_info(),
+                pause_registry : ctx.accounts.pause_registry.to_account_info(),
+                router_authority : ctx.accounts.router_authority.to_account_info(),
+                pool_state : ctx.accounts.pool_state.to_account_info(),
+                pool_authority : ctx.accounts.pool_authority.to_account_info(),
+                pool_vault : ctx.accounts.pool_vault.to_account_info(),
+                receiver_token : ctx.accounts.account_token.to_account_info(),
+                price_feed : ctx.accounts.price_feed.to_account_info(),
+                token_price : ctx.accounts.token_price.to_account_info(),
+                pool_program : ctx.accounts.pool_program.to_account_info(),
+                token_program : ctx.accounts.token_program.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.router_program.to_account_info(), cpi_accounts, signer_seeds)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+            router::cpi::plugin_close_position_by_liquidator(cpi_ctx , pool , account , side , position.size , account)?;
+            return Ok(())
         }
-        let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts: PositionManagement<'_> = PositionManagement{
-            state : ctx.accounts.router_program.to_account_info(),
-            authorized_account : addresses,
-            user : ctx.accounts.router_program.to_account_info()
-        };
-        let receiver : Pubkey = Pubkey::default(); // change this to the program Pubkey that  you set in the end 
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, PositionManagement<'_>> = CpiContext::new(cpi_program, cpi_accounts);
-        router::cpi::plugin_close_position_by_liquidator(cpi_ctx , pool ,  side , size , receiver);
-        // external call to pool 
 
-
-        Ok(())
+        let cpi_accounts = liquidity_position_util::cpi::accounts::LiquidatePosition{
+            liquidator_authority : ctx.accounts.liquidator_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            fee_receiver_token : ctx.accounts.fee_receiver_token.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds);
+        liquidity_position_util::cpi::liquidate_position(cpi_ctx , account , side , decrease_index_price_x96 , _fee_reciever)
     }
 
+    // Additional functions as per your contract
+}
 
-    pub fn _choose_index_price(ctx: Context<GovernanceAction> , token : Pubkey , side : bool ) -> Result<u64>{
-        if side{
-
-        }
-    Ok(100)
-    }
-
-    pub fn _choose_funding_rate_growth(ctx: Context<GovernanceAction> , pool : Pubkey , side : bool) -> Result<u64>{
-        if side{
+// Index price a position on `side` is decreased at
+pub fn _choose_index_price(price_feed : &PriceFeed , token_price : &TokenPrice , token : Pubkey , side : bool , oracles : &[AccountInfo]) -> Result<u128> {
+    // longs are decreased at the min price and shorts at the max price
+    price_feed::get_index_price(price_feed, token_price, token, !side, oracles)
+}
 
-        }
-    Ok(100)
-    }
+pub fn _choose_funding_rate_growth(pool_state : &PoolState , side : bool) -> i128 {
+    position_util::choose_previous_global_funding_rate_growth_x96(&pool_state.global_position, side)
+}
 
-    pub fn _require_liquidatable(ctx: Context<GovernanceAction> , token : Pubkey , side : bool , _account : Pubkey , _margin:u64 , _size : u64 , _enrty_price_x96 :u64, _decrease_price_x96:u64) -> Result<u64>{
-        if side{
+// Errors unless the position's margin, after funding, no longer covers its maintenance margin
+pub fn _require_liquidatable(pool_state : &PoolState , side : bool , position : &Position , decrease_index_price_x96 : u128) -> Result<()> {
+    let funding_fee: i128 = position_util::calculate_funding_fee(_choose_funding_rate_growth(pool_state, side), position.entry_funding_rate_growth_x96, position.size);
+    let margin: i128 = (position.margin as i128).checked_add(funding_fee).ok_or(MyError::InvalidOperation)?;
+    position_util::validate_position_liquidate_maintain_margin_rate(&pool_state.market_config.base_config, &MaintainMarginRateParameter{
+        margin,
+        side,
+        size : position.size,
+        entry_price_x96 : position.entry_price_x96,
+        decrease_price_x96 : decrease_index_price_x96,
+        trading_fee_rate : pool_state.market_config.fee_rate_config.trading_fee_rate,
+        liquidatable_position : true,
+    })
+}
 
-        }
-    Ok(100)
-    }
+// Errors with `RiskRateTooLow` before reaching the pool unless the liquidity position can be liquidated
+pub fn _require_liquidity_position_liquidatable(pool_state : &PoolState , position : &LiquidityPosition) -> Result<()> {
+    let realized_pnl: i128 = liquidity_position_util::liquidity_position_util::calculate_realized_pnl(&pool_state.global_liqudity_position, position)?;
+    let margin: i128 = (position.margin as i128).checked_add(realized_pnl).ok_or(MyError::InvalidOperation)?;
+    liquidity_position_util::liquidity_position_util::validate_liquidity_position_risk_rate(&pool_state.market_config.base_config, margin, position.liquidity, true)
+}
 
-    pub fn _has_unrealized_profit(ctx: Context<GovernanceAction> , _entry_price: u64 , side : bool , _index_price :u64 ) -> Result<bool>{
-        if side{
-            return Ok(_index_price > _entry_price);
-        }
-    
-    return Ok(_entry_price > _index_price);
+pub fn _has_unrealized_profit(_entry_price : u128 , side : bool , _index_price : u128) -> bool {
+    if side{
+        return _index_price > _entry_price;
     }
-
-
-    // Additional functions as per your contract
+    _entry_price > _index_price
 }
 
 // Contract state to hold the list of authorized addresses
@@ -152,6 +224,8 @@ pub struct GovernanceAction<'info> {
     #[account(mut)]
     pub governance_state: Account<'info, State>,
     pub user: Signer<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 
@@ -164,18 +238,18 @@ pub struct Initialize<'info> {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, State>,
     pub user: Signer<'info>,
-
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 // Context struct for UpdatePriceFeed function
 #[derive(Accounts)]
 pub struct UpdatePriceFeed<'info> {
-     // Adjust space as needed
-    /// CHECK
-    #[account(signer)]
-    pub authorized_account: AccountInfo<'info>,
+    // only queued changes reach this, signed by the governance timelock once their eta passed
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump, seeds::program = governance::ID)]
+    pub timelock_authority: Signer<'info>,
+    #[account(mut)]
     pub state: Account<'info, State>,
-    pub user: Signer<'info>,
 }
 
 // Context struct for UpdateExecutor function
@@ -186,6 +260,8 @@ pub struct UpdateExecutor<'info> {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, State>,
     pub user: Signer<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 // Context struct for LiquidateLiquidityPosition function
@@ -196,24 +272,85 @@ pub struct LiquidateLiquidityPosition<'info> {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, State>,
     pub user: Signer<'info>,
+    /// CHECK: PDA this program signs pool calls with
+    #[account(seeds = [LIQUIDATOR_AUTHORITY_SEED], bump)]
+    pub liquidator_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub pool_state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub fee_receiver_token: Account<'info, TokenAccount>,
+    pub pool_program: Program<'info, Pool>,
+    pub token_program: Program<'info, Token>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    #[account(constraint = price_feed.key() == state.price_feed @ MyError::InvalidOperation)]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub token_price: Account<'info, TokenPrice>,
 }
 
 // Context struct for LiquidatePosition function
 #[derive(Accounts)]
 pub struct LiquidatePosition<'info> {
-        /// CHECK
-        
+        /// CHECK: owner of the position being liquidated
         pub authorized_account: AccountInfo<'info>,
 
         pub state: Account<'info, State>,
         pub user: Signer<'info>,
+        /// CHECK: PDA this program signs router and pool calls with
+        #[account(seeds = [LIQUIDATOR_AUTHORITY_SEED], bump)]
+        pub liquidator_authority: AccountInfo<'info>,
+        pub router_state: Account<'info, ContractState>,
         pub router_program: Program<'info, Router>,
+        /// CHECK: PDA the router signs pool calls with, validated by the router
+        pub router_authority: AccountInfo<'info>,
+        #[account(mut)]
+        pub pool_state: Account<'info, PoolState>,
+        /// CHECK: PDA owning the pool vault, validated by the pool
+        pub pool_authority: AccountInfo<'info>,
+        #[account(mut)]
+        pub pool_vault: Account<'info, TokenAccount>,
+        #[account(mut)]
+        pub fee_receiver_token: Account<'info, TokenAccount>,
+        // the owner's token account, paid the margin left when a position in profit is closed
+        #[account(mut)]
+        pub account_token: Account<'info, TokenAccount>,
+        pub pool_program: Program<'info, Pool>,
+        pub token_program: Program<'info, Token>,
+        #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+        pub pause_registry: Account<'info, PauseRegistry>,
+        #[account(constraint = price_feed.key() == state.price_feed @ MyError::InvalidOperation)]
+        pub price_feed: Account<'info, PriceFeed>,
+        pub token_price: Account<'info, TokenPrice>,
 }
 
+// Context struct for AdlPosition function
 #[derive(Accounts)]
-pub struct ClosePositionByLiquidatorCpiContext<'info> {
-    
-    pub router_program: Program<'info, Router>,
+pub struct AdlPosition<'info> {
+    pub state: Account<'info, State>,
+    pub user: Signer<'info>,
+    /// CHECK: PDA this program signs pool calls with
+    #[account(seeds = [LIQUIDATOR_AUTHORITY_SEED], bump)]
+    pub liquidator_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub pool_state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub pool_vault: Account<'info, TokenAccount>,
+    // the deleveraged owner's token account, paid the released margin
+    #[account(mut)]
+    pub account_token: Account<'info, TokenAccount>,
+    pub pool_program: Program<'info, Pool>,
+    pub token_program: Program<'info, Token>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    #[account(constraint = price_feed.key() == state.price_feed @ MyError::InvalidOperation)]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub token_price: Account<'info, TokenPrice>,
 }
 
 
@@ -226,5 +363,7 @@ pub enum MyError {
     InvalidOperation,
     #[msg("Program Already initilized")]
     AlreadyInitlized,
+    #[msg("Position not found")]
+    PositionNotFound,
     // Add other custom errors
 }
@@ -1,8 +1,8 @@
 use anchor_lang::prelude::*;
 mod helper;
 mod math;
-mod liquidity_position_util;
-mod position_util;
+pub mod liquidity_position_util;
+pub mod position_util;
 pub mod interfaces{
     pub mod IConfigurable;
 pub mod IMarketLiquidityPosition; 
@@ -17,6 +17,866 @@ pub mod IMarketPosition;}
 
 declare_id!("XNiBJSgxaaUkYfX8outPevtBcmao6LV1UrTQiyJ2YJs");
 
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use governance::{Governance, PauseRegistry, GOVERNANCE_SEED, PAUSE_LIQUIDITY, PAUSE_REGISTRY_SEED, TIMELOCK_AUTHORITY_SEED};
+use anchor_lang::solana_program::program::set_return_data;
+use price_feed::{TokenPrice, PRICE_FEED_SEED, TOKEN_PRICE_SEED};
+use price_utils::{MarketPrice, TradeQuote};
+use position_util::{State as PoolState, LiquidateParameter, ErrorCode as PoolError};
+
+/// Liquidator program allowed to liquidate positions of the pool (Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnR)
+pub const LIQUIDATOR_PROGRAM_ID: Pubkey = Pubkey::new_from_array([218, 7, 92, 178, 255, 94, 198, 129, 118, 19, 222, 83, 11, 105, 42, 135, 53, 71, 119, 105, 218, 71, 67, 12, 189, 129, 84, 51, 92, 74, 131, 38]);
+/// Seed of the PDA the liquidator program signs with
+pub const LIQUIDATOR_AUTHORITY_SEED: &[u8] = b"liquidator_authority";
+/// Seed of the PDA owning the pool vault
+pub const POOL_AUTHORITY_SEED: &[u8] = b"pool_authority";
+/// Router program allowed to change positions of the pool (Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS)
+pub const ROUTER_PROGRAM_ID: Pubkey = Pubkey::new_from_array([218, 7, 92, 178, 255, 94, 198, 129, 118, 19, 222, 83, 11, 105, 42, 135, 53, 71, 119, 105, 218, 71, 67, 12, 189, 129, 84, 51, 92, 74, 131, 39]);
+/// Seed of the PDA the router program signs with
+pub const ROUTER_AUTHORITY_SEED: &[u8] = b"router_authority";
+
+#[program]
+pub mod pool {
+    use super::*;
+
+    // Sets the index token the market is priced in, it cannot be changed once set
+    pub fn set_market_token(ctx: Context<SetMarketToken>, token: Pubkey) -> Result<()> {
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        require!(state.token == Pubkey::default(), PoolError::MarketTokenAlreadySet);
+        state.token = token;
+        emit!(MarketTokenSet{ pool : state.key() , token });
+        Ok(())
+    }
+
+    // Replaces the market config, only through a change queued in the governance timelock, and rebuilds the price vertices at the market token's max index price
+    pub fn update_market_config<'info>(ctx: Context<'_, '_, 'info, 'info, UpdateMarketConfig<'info>>, market_config: position_util::MarketConfig) -> Result<()> {
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.token, true, ctx.remaining_accounts)?;
+        let pool: Pubkey = ctx.accounts.state.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        state.market_config = market_config;
+        let price_config: position_util::MarketPriceConfig = state.market_config.price_config.clone();
+        liquidity_position_util::_change_price_vertices(state, &price_config, pool, index_price_x96);
+        emit!(MarketConfigChanged{ pool });
+        Ok(())
+    }
+
+    // Opens or increases the `account`'s position on `side` for the router at the market token's index price, returning the trade price.
+    // The margin must already be in the pool vault, on top of the balance the pool accounts for.
+    pub fn increase_position<'info>(ctx: Context<'_, '_, 'info, 'info, IncreasePosition<'info>>, account: Pubkey, side: bool, margin_delta: u128, size_delta: u128) -> Result<u128> {
+        // longs open at the max price and shorts at the min price
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.token, side, ctx.remaining_accounts)?;
+        let pool: Pubkey = ctx.accounts.state.key();
+        let vault_amount: u128 = ctx.accounts.pool_vault.amount as u128;
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let usd_balance_after: u128 = state.usd_balance.checked_add(margin_delta).ok_or(PoolError::Overflow)?;
+        require!(vault_amount >= usd_balance_after, PoolError::InsufficientVaultBalance);
+
+        let index: usize = match position_util::get_position_index(state, account, side) {
+            Some(index) => index,
+            None => {
+                state.positions.push(position_util::Position{
+                    account,
+                    side,
+                    margin : 0,
+                    size : 0,
+                    entry_price_x96 : 0,
+                    entry_funding_rate_growth_x96 : 0,
+                });
+                state.positions.len() - 1
+            }
+        };
+        let mut position: position_util::Position = state.positions[index].clone();
+        let market_config: position_util::MarketConfig = state.market_config.clone();
+        let parameter: position_util::IncreasePositionParameter = position_util::IncreasePositionParameter{
+            market : pool,
+            account,
+            side,
+            margin_delta,
+            size_delta,
+            index_price_x96,
+        };
+        let trade_price_x96: u128 = position_util::increase_position(state, &market_config, &parameter, &mut position, index)?;
+        state.usd_balance = usd_balance_after;
+
+        emit!(PositionIncreased{
+            pool,
+            account,
+            side,
+            margin_delta,
+            size_delta,
+            trade_price_x96,
+            margin_after : position.margin,
+            size_after : position.size,
+        });
+        Ok(trade_price_x96)
+    }
+
+    // Decreases the `account`'s position on `side` for the router at the market token's index price, paying the released margin
+    // to `receiver`'s token account and returning the trade price
+    pub fn decrease_position<'info>(ctx: Context<'_, '_, 'info, 'info, DecreasePosition<'info>>, account: Pubkey, side: bool, margin_delta: u128, size_delta: u128, receiver: Pubkey) -> Result<u128> {
+        require!(ctx.accounts.receiver_token.owner == receiver, PoolError::InvalidFeeReceiver);
+        // longs close at the min price and shorts at the max price
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.token, !side, ctx.remaining_accounts)?;
+        let pool: Pubkey = ctx.accounts.state.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let index: usize = position_util::get_position_index(state, account, side).ok_or(PoolError::PositionNotFound)?;
+        let mut position: position_util::Position = state.positions[index].clone();
+        let market_config: position_util::MarketConfig = state.market_config.clone();
+        let parameter: position_util::DecreasePositionParameter = position_util::DecreasePositionParameter{
+            market : pool,
+            account,
+            side,
+            margin_delta,
+            size_delta,
+            receiver,
+            index_price_x96,
+        };
+        let (trade_price_x96, margin_paid) = position_util::decrease_position(state, &market_config, &parameter, &mut position)?;
+        if position.size == 0 {
+            state.positions.remove(index);
+        } else {
+            state.positions[index] = position.clone();
+        }
+
+        state.usd_balance = state.usd_balance.checked_sub(margin_paid).ok_or(PoolError::Overflow)?;
+        let bump: u8 = ctx.bumps.pool_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.pool_vault.to_account_info(),
+            to : ctx.accounts.receiver_token.to_account_info(),
+            authority : ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, u64::try_from(margin_paid).map_err(|_| PoolError::Overflow)?)?;
+
+        emit!(PositionDecreased{
+            pool,
+            account,
+            side,
+            margin_delta,
+            size_delta,
+            trade_price_x96,
+            margin_after : position.margin,
+            size_after : position.size,
+            receiver,
+            margin_paid,
+        });
+        Ok(trade_price_x96)
+    }
+
+    // Opens or increases the liquidity position `position_id` for the router at the market token's max index price, returning
+    // the margin after. `account` only owns a newly opened position, the router checks the NFT holder of existing ones. The margin must already be in the pool vault, on top of the balance the pool accounts for.
+    pub fn increase_liquidity_position<'info>(ctx: Context<'_, '_, 'info, 'info, IncreasePosition<'info>>, account: Pubkey, position_id: u64, margin_delta: u128, liquidity_delta: u128) -> Result<u128> {
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.token, true, ctx.remaining_accounts)?;
+        let pool: Pubkey = ctx.accounts.state.key();
+        let vault_amount: u128 = ctx.accounts.pool_vault.amount as u128;
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let usd_balance_after: u128 = state.usd_balance.checked_add(margin_delta).ok_or(PoolError::Overflow)?;
+        require!(vault_amount >= usd_balance_after, PoolError::InsufficientVaultBalance);
+
+        let index: usize = match position_util::get_liquidity_position_index(state, position_id) {
+            Some(index) => index,
+            None => {
+                state.liquidity_positions.push(position_util::LiquidityPosition{
+                    id : position_id,
+                    account,
+                    margin : 0,
+                    liquidity : 0,
+                    entry_unrealized_pnl_growth_x64 : 0,
+                });
+                state.liquidity_positions.len() - 1
+            }
+        };
+        let mut market_config: position_util::MarketConfig = state.market_config.clone();
+        let parameter = liquidity_position_util::IncreaseLiquidityPositionContext{
+            market : pool,
+            margin_delta,
+            liquidity_delta,
+            index_price_x96,
+        };
+        let margin_after: u128 = liquidity_position_util::increase_liquidity_position(state, &mut market_config, &parameter, index)?;
+        state.usd_balance = usd_balance_after;
+
+        emit!(LiquidityPositionIncreased{
+            pool,
+            account,
+            position_id,
+            margin_delta,
+            liquidity_delta,
+            margin_after,
+            liquidity_after : state.liquidity_positions[index].liquidity,
+        });
+        Ok(margin_after)
+    }
+
+    // Decreases the liquidity position `position_id` for the router at the market token's max index price, paying the released
+    // margin to `receiver`'s token account. Removing all the liquidity closes the position and pays out its whole margin.
+    // The router checks the caller holds the position NFT.
+    pub fn decrease_liquidity_position<'info>(ctx: Context<'_, '_, 'info, 'info, DecreasePosition<'info>>, position_id: u64, margin_delta: u128, liquidity_delta: u128, receiver: Pubkey) -> Result<u128> {
+        require!(ctx.accounts.receiver_token.owner == receiver, PoolError::InvalidFeeReceiver);
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.token, true, ctx.remaining_accounts)?;
+        let pool: Pubkey = ctx.accounts.state.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let index: usize = position_util::get_liquidity_position_index(state, position_id).ok_or(PoolError::PositionNotFound)?;
+        let account: Pubkey = state.liquidity_positions[index].account;
+
+        let market_config: position_util::MarketConfig = state.market_config.clone();
+        let parameter = liquidity_position_util::DecreaseLiquidityPositionParameter{
+            market : pool,
+            account_index : index,
+            margin_delta,
+            liquidity_delta,
+            index_price_x96,
+        };
+        let (mut margin_after, mut margin_paid) = liquidity_position_util::decrease_liquidity_position(state, &market_config.price_config, &parameter, index)?;
+        let liquidity_after: u128 = state.liquidity_positions[index].liquidity;
+        if liquidity_after == 0 {
+            margin_paid = margin_paid.checked_add(margin_after).ok_or(PoolError::Overflow)?;
+            margin_after = 0;
+            state.liquidity_positions.remove(index);
+        } else {
+            liquidity_position_util::validate_liquidity_position_risk_rate(&market_config.base_config, margin_after as i128, liquidity_after, false)?;
+        }
+
+        state.usd_balance = state.usd_balance.checked_sub(margin_paid).ok_or(PoolError::Overflow)?;
+        let bump: u8 = ctx.bumps.pool_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.pool_vault.to_account_info(),
+            to : ctx.accounts.receiver_token.to_account_info(),
+            authority : ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, u64::try_from(margin_paid).map_err(|_| PoolError::Overflow)?)?;
+
+        emit!(LiquidityPositionDecreased{
+            pool,
+            account,
+            position_id,
+            margin_delta,
+            liquidity_delta,
+            margin_after,
+            liquidity_after,
+            receiver,
+            margin_paid,
+        });
+        Ok(margin_paid)
+    }
+
+    // Adds `margin_delta` to the liquidity position `position_id` for the router when positive, the margin must already be in the
+    // pool vault. When negative, withdraws it to `receiver`'s token account as long as the position stays under the max risk rate.
+    pub fn adjust_liquidity_position_margin<'info>(ctx: Context<'_, '_, 'info, 'info, DecreasePosition<'info>>, position_id: u64, margin_delta: i128, receiver: Pubkey) -> Result<()> {
+        require!(ctx.accounts.receiver_token.owner == receiver, PoolError::InvalidFeeReceiver);
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.token, true, ctx.remaining_accounts)?;
+        let pool: Pubkey = ctx.accounts.state.key();
+        let vault_amount: u128 = ctx.accounts.pool_vault.amount as u128;
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let index: usize = position_util::get_liquidity_position_index(state, position_id).ok_or(PoolError::PositionNotFound)?;
+        let account: Pubkey = state.liquidity_positions[index].account;
+
+        let mut market_config: position_util::MarketConfig = state.market_config.clone();
+        let margin_after: u128 = if margin_delta >= 0 {
+            let usd_balance_after: u128 = state.usd_balance.checked_add(margin_delta.unsigned_abs()).ok_or(PoolError::Overflow)?;
+            require!(vault_amount >= usd_balance_after, PoolError::InsufficientVaultBalance);
+            let parameter = liquidity_position_util::IncreaseLiquidityPositionContext{
+                market : pool,
+                margin_delta : margin_delta.unsigned_abs(),
+                liquidity_delta : 0,
+                index_price_x96,
+            };
+            let margin_after: u128 = liquidity_position_util::increase_liquidity_position(state, &mut market_config, &parameter, index)?;
+            state.usd_balance = usd_balance_after;
+            margin_after
+        } else {
+            let parameter = liquidity_position_util::DecreaseLiquidityPositionParameter{
+                market : pool,
+                account_index : index,
+                margin_delta : margin_delta.unsigned_abs(),
+                liquidity_delta : 0,
+                index_price_x96,
+            };
+            let (margin_after, margin_paid) = liquidity_position_util::decrease_liquidity_position(state, &market_config.price_config, &parameter, index)?;
+            liquidity_position_util::validate_liquidity_position_risk_rate(&market_config.base_config, margin_after as i128, state.liquidity_positions[index].liquidity, false)?;
+
+            state.usd_balance = state.usd_balance.checked_sub(margin_paid).ok_or(PoolError::Overflow)?;
+            let bump: u8 = ctx.bumps.pool_authority;
+            let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+            let cpi_accounts: Transfer<'_> = Transfer{
+                from : ctx.accounts.pool_vault.to_account_info(),
+                to : ctx.accounts.receiver_token.to_account_info(),
+                authority : ctx.accounts.pool_authority.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+            token::transfer(cpi_ctx, u64::try_from(margin_paid).map_err(|_| PoolError::Overflow)?)?;
+            margin_after
+        };
+
+        emit!(LiquidityPositionMarginAdjusted{
+            pool,
+            account,
+            position_id,
+            margin_delta,
+            margin_after,
+            receiver,
+        });
+        Ok(())
+    }
+
+    // Liquidates the position at `decrease_index_price_x96`, paying the liquidation execution fee to `fee_receiver`
+    pub fn liquidate_position(ctx: Context<LiquidatePosition>, account: Pubkey, side: bool, decrease_index_price_x96: u128, fee_receiver: Pubkey) -> Result<()> {
+        require!(ctx.accounts.fee_receiver_token.owner == fee_receiver, PoolError::InvalidFeeReceiver);
+        let pool: Pubkey = ctx.accounts.state.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let index: usize = position_util::get_position_index(state, account, side).ok_or(PoolError::PositionNotFound)?;
+        let mut position: position_util::Position = state.positions[index].clone();
+
+        let required_funding_fee: i128 = position_util::calculate_funding_fee(
+            position_util::choose_previous_global_funding_rate_growth_x96(&state.global_position, side),
+            position.entry_funding_rate_growth_x96,
+            position.size,
+        );
+        let market_config: position_util::MarketConfig = state.market_config.clone();
+        let trading_fee_state = position_util::build_trading_fee_state(&market_config.fee_rate_config, account, 0, 0);
+        let parameter: LiquidateParameter = LiquidateParameter{
+            market : pool,
+            account,
+            side,
+            decrease_index_price_x96,
+            required_funding_fee,
+            fee_receiver,
+        };
+        let liquidated_size: u128 = position_util::liquidate_position(state, &market_config, &mut position, &trading_fee_state, &parameter)?;
+        if position.size == 0 {
+            state.positions.remove(index);
+        } else {
+            state.positions[index] = position;
+        }
+
+        let liquidation_execution_fee: u64 = market_config.base_config.liquidation_execution_fee;
+        state.usd_balance = state.usd_balance.checked_sub(liquidation_execution_fee as u128).ok_or(PoolError::Overflow)?;
+        let bump: u8 = ctx.bumps.pool_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.pool_vault.to_account_info(),
+            to : ctx.accounts.fee_receiver_token.to_account_info(),
+            authority : ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, liquidation_execution_fee)?;
+
+        emit!(PositionLiquidated{
+            pool,
+            account,
+            side,
+            decrease_index_price_x96,
+            liquidated_size,
+            fee_receiver,
+            liquidation_execution_fee,
+        });
+        Ok(())
+    }
+
+    // Force-reduces the `account`'s position on `side`, which must be the top ranked profitable position at `mark_price_x96`, while the
+    // liquidation fund deficit is beyond the threshold, paying the margin released by the reduction to the owner's `account_token`
+    pub fn adl_position(ctx: Context<AutoDeleveragePosition>, account: Pubkey, side: bool, mark_price_x96: u128) -> Result<()> {
+        let pool: Pubkey = ctx.accounts.state.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        require!(position_util::is_adl_required(state, &state.market_config.base_config), PoolError::AdlNotRequired);
+        let index: usize = position_util::select_adl_position(state, mark_price_x96).ok_or(PoolError::PositionNotFound)?;
+        require!(state.positions[index].account == account && state.positions[index].side == side, PoolError::AdlPositionNotSelected);
+        require!(ctx.accounts.account_token.owner == account, PoolError::InvalidFeeReceiver);
+        let market_config: position_util::MarketConfig = state.market_config.clone();
+        let (size_delta, realized_pnl, margin_paid) = position_util::auto_deleverage_position(state, &market_config, pool, index, mark_price_x96)?;
+
+        state.usd_balance = state.usd_balance.checked_sub(margin_paid).ok_or(PoolError::Overflow)?;
+        let bump: u8 = ctx.bumps.pool_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.pool_vault.to_account_info(),
+            to : ctx.accounts.account_token.to_account_info(),
+            authority : ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, u64::try_from(margin_paid).map_err(|_| PoolError::Overflow)?)?;
+
+        emit!(PositionAutoDeleveraged{
+            pool,
+            account,
+            side,
+            size_delta,
+            mark_price_x96,
+            realized_pnl,
+            margin_paid,
+            liquidation_fund_after : state.global_liquidation_fund.liquidation_fund,
+        });
+        Ok(())
+    }
+
+    // Deposits `liquidity_delta` into the risk buffer fund for the signer, locking the position for the lock period
+    pub fn increase_risk_buffer_fund_position(ctx: Context<IncreaseRiskBufferFundPosition>, liquidity_delta: u128) -> Result<()> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, ctx.accounts.state.key(), PAUSE_LIQUIDITY)?;
+        let amount: u64 = u64::try_from(liquidity_delta).map_err(|_| PoolError::Overflow)?;
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.account_token.to_account_info(),
+            to : ctx.accounts.pool_vault.to_account_info(),
+            authority : ctx.accounts.account.to_account_info(),
+        };
+        token::transfer(CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts), amount)?;
+
+        let pool: Pubkey = ctx.accounts.state.key();
+        let account: Pubkey = ctx.accounts.account.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let (liquidity_after, unlock_time) = position_util::increase_risk_buffer_fund_position(state, account, liquidity_delta, Clock::get()?.unix_timestamp)?;
+        state.usd_balance = state.usd_balance.checked_add(liquidity_delta).ok_or(PoolError::Overflow)?;
+
+        emit!(RiskBufferFundPositionIncreased{
+            pool,
+            account,
+            liquidity_delta,
+            liquidity_after,
+            unlock_time,
+        });
+        Ok(())
+    }
+
+    // Withdraws `liquidity_delta` of the signer's unlocked risk buffer fund position, paying its share of the fund to `account_token`
+    pub fn decrease_risk_buffer_fund_position(ctx: Context<DecreaseRiskBufferFundPosition>, liquidity_delta: u128) -> Result<()> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, ctx.accounts.state.key(), PAUSE_LIQUIDITY)?;
+        let pool: Pubkey = ctx.accounts.state.key();
+        let account: Pubkey = ctx.accounts.account.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let amount: u128 = position_util::decrease_risk_buffer_fund_position(state, account, liquidity_delta, Clock::get()?.unix_timestamp)?;
+        state.usd_balance = state.usd_balance.checked_sub(amount).ok_or(PoolError::Overflow)?;
+
+        let bump: u8 = ctx.bumps.pool_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.pool_vault.to_account_info(),
+            to : ctx.accounts.account_token.to_account_info(),
+            authority : ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, u64::try_from(amount).map_err(|_| PoolError::Overflow)?)?;
+
+        emit!(RiskBufferFundPositionDecreased{
+            pool,
+            account,
+            liquidity_delta,
+            amount,
+            receiver : ctx.accounts.account_token.key(),
+        });
+        Ok(())
+    }
+
+    // Deposits `liquidity_delta` into the risk buffer fund for `account` through the router, locking the position for the lock period.
+    // The liquidity must already be in the pool vault, on top of the balance the pool accounts for.
+    pub fn increase_risk_buffer_fund_position_by_router(ctx: Context<IncreaseRiskBufferFundPositionByRouter>, account: Pubkey, liquidity_delta: u128) -> Result<()> {
+        let pool: Pubkey = ctx.accounts.state.key();
+        let vault_amount: u128 = ctx.accounts.pool_vault.amount as u128;
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let usd_balance_after: u128 = state.usd_balance.checked_add(liquidity_delta).ok_or(PoolError::Overflow)?;
+        require!(vault_amount >= usd_balance_after, PoolError::InsufficientVaultBalance);
+        let (liquidity_after, unlock_time) = position_util::increase_risk_buffer_fund_position(state, account, liquidity_delta, Clock::get()?.unix_timestamp)?;
+        state.usd_balance = usd_balance_after;
+
+        emit!(RiskBufferFundPositionIncreased{
+            pool,
+            account,
+            liquidity_delta,
+            liquidity_after,
+            unlock_time,
+        });
+        Ok(())
+    }
+
+    // Withdraws `liquidity_delta` of the `account`'s unlocked risk buffer fund position through the router, paying its share of the
+    // fund to `receiver`'s token account
+    pub fn decrease_risk_buffer_fund_position_by_router(ctx: Context<DecreaseRiskBufferFundPositionByRouter>, account: Pubkey, liquidity_delta: u128, receiver: Pubkey) -> Result<()> {
+        require!(ctx.accounts.receiver_token.owner == receiver, PoolError::InvalidFeeReceiver);
+        let pool: Pubkey = ctx.accounts.state.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let amount: u128 = position_util::decrease_risk_buffer_fund_position(state, account, liquidity_delta, Clock::get()?.unix_timestamp)?;
+        state.usd_balance = state.usd_balance.checked_sub(amount).ok_or(PoolError::Overflow)?;
+
+        let bump: u8 = ctx.bumps.pool_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.pool_vault.to_account_info(),
+            to : ctx.accounts.receiver_token.to_account_info(),
+            authority : ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, u64::try_from(amount).map_err(|_| PoolError::Overflow)?)?;
+
+        emit!(RiskBufferFundPositionDecreased{
+            pool,
+            account,
+            liquidity_delta,
+            amount,
+            receiver : ctx.accounts.receiver_token.key(),
+        });
+        Ok(())
+    }
+
+    // Liquidates the liquidity position `position_id`, paying the liquidation execution fee to `fee_receiver` and rebuilding the price vertices at `index_price_x96`
+    pub fn liquidate_liquidity_position(ctx: Context<LiquidatePosition>, position_id: u64, fee_receiver: Pubkey, index_price_x96: u128) -> Result<()> {
+        require!(ctx.accounts.fee_receiver_token.owner == fee_receiver, PoolError::InvalidFeeReceiver);
+        let pool: Pubkey = ctx.accounts.state.key();
+        let state: &mut Account<'_, PoolState> = &mut ctx.accounts.state;
+        let index: usize = position_util::get_liquidity_position_index(state, position_id).ok_or(PoolError::PositionNotFound)?;
+        let account: Pubkey = state.liquidity_positions[index].account;
+
+        let market_config: position_util::MarketConfig = state.market_config.clone();
+        let parameter = liquidity_position_util::LiquidateLiquidityPositionParameter{
+            market : pool,
+            position_id,
+            fee_receiver,
+            index_price_x96,
+        };
+        let (liquidation_execution_fee, liquidity_delta) = liquidity_position_util::liquidate_liquidity_position(state, &parameter, index, &market_config)?;
+
+        state.usd_balance = state.usd_balance.checked_sub(liquidation_execution_fee as u128).ok_or(PoolError::Overflow)?;
+        let bump: u8 = ctx.bumps.pool_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[POOL_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.pool_vault.to_account_info(),
+            to : ctx.accounts.fee_receiver_token.to_account_info(),
+            authority : ctx.accounts.pool_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, liquidation_execution_fee)?;
+
+        emit!(LiquidityPositionLiquidated{
+            pool,
+            account,
+            position_id,
+            liquidity_delta,
+            liquidator : ctx.accounts.liquidator_authority.key(),
+            fee_receiver,
+            liquidation_execution_fee,
+        });
+        Ok(())
+    }
+
+    // View: returns the borsh encoded `TradeQuote` of a `side` trade of `size_delta` at the market token's current index price, without changing the pool
+    pub fn quote_trade<'info>(ctx: Context<'_, '_, 'info, 'info, MarketView<'info>>, side: bool, size_delta: u128) -> Result<()> {
+        // longs open at the max price and shorts at the min price
+        let index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.token, side, ctx.remaining_accounts)?;
+        let state: &PoolState = &ctx.accounts.state;
+        let quote: TradeQuote = price_utils::quote_trade(
+            &state.price_state,
+            &state.global_liqudity_position,
+            &state.market_config.price_config,
+            state.market_config.fee_rate_config.trading_fee_rate,
+            side,
+            size_delta,
+            index_price_x96,
+        )?;
+        set_return_data(&quote.try_to_vec()?);
+        Ok(())
+    }
+
+    // View: returns the borsh encoded `MarketPrice` of the pool, longs at the market token's max index price and shorts at its min index price
+    pub fn get_market_price<'info>(ctx: Context<'_, '_, 'info, 'info, MarketView<'info>>) -> Result<()> {
+        let token: Pubkey = ctx.accounts.state.token;
+        let long_index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, token, true, ctx.remaining_accounts)?;
+        let short_index_price_x96: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, token, false, ctx.remaining_accounts)?;
+        let state: &PoolState = &ctx.accounts.state;
+        let market_price: MarketPrice = price_utils::get_market_price(&state.price_state, &state.global_liqudity_position, long_index_price_x96, short_index_price_x96);
+        set_return_data(&market_price.try_to_vec()?);
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct IncreasePosition<'info> {
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump, seeds::program = ROUTER_PROGRAM_ID)]
+    pub router_authority: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(seeds = [PRICE_FEED_SEED], bump, seeds::program = price_feed::ID)]
+    pub price_feed: Account<'info, price_feed::PriceFeed>,
+    #[account(seeds = [TOKEN_PRICE_SEED, token_price.token.as_ref()], bump, seeds::program = price_feed::ID)]
+    pub token_price: Account<'info, TokenPrice>,
+}
+
+#[derive(Accounts)]
+pub struct DecreasePosition<'info> {
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump, seeds::program = ROUTER_PROGRAM_ID)]
+    pub router_authority: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub receiver_token: Account<'info, TokenAccount>,
+    #[account(seeds = [PRICE_FEED_SEED], bump, seeds::program = price_feed::ID)]
+    pub price_feed: Account<'info, price_feed::PriceFeed>,
+    #[account(seeds = [TOKEN_PRICE_SEED, token_price.token.as_ref()], bump, seeds::program = price_feed::ID)]
+    pub token_price: Account<'info, TokenPrice>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidatePosition<'info> {
+    #[account(seeds = [LIQUIDATOR_AUTHORITY_SEED], bump, seeds::program = LIQUIDATOR_PROGRAM_ID)]
+    pub liquidator_authority: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub fee_receiver_token: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SetMarketToken<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateMarketConfig<'info> {
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump, seeds::program = governance::ID)]
+    pub timelock_authority: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    #[account(seeds = [PRICE_FEED_SEED], bump, seeds::program = price_feed::ID)]
+    pub price_feed: Account<'info, price_feed::PriceFeed>,
+    #[account(seeds = [TOKEN_PRICE_SEED, token_price.token.as_ref()], bump, seeds::program = price_feed::ID)]
+    pub token_price: Account<'info, TokenPrice>,
+}
+
+#[derive(Accounts)]
+pub struct MarketView<'info> {
+    pub state: Account<'info, PoolState>,
+    #[account(seeds = [PRICE_FEED_SEED], bump, seeds::program = price_feed::ID)]
+    pub price_feed: Account<'info, price_feed::PriceFeed>,
+    #[account(seeds = [TOKEN_PRICE_SEED, token_price.token.as_ref()], bump, seeds::program = price_feed::ID)]
+    pub token_price: Account<'info, TokenPrice>,
+}
+
+#[derive(Accounts)]
+pub struct AutoDeleveragePosition<'info> {
+    #[account(seeds = [LIQUIDATOR_AUTHORITY_SEED], bump, seeds::program = LIQUIDATOR_PROGRAM_ID)]
+    pub liquidator_authority: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    // token account of the deleveraged position's owner, checked against the selected position
+    #[account(mut)]
+    pub account_token: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct IncreaseRiskBufferFundPosition<'info> {
+    pub account: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    #[account(mut, constraint = account_token.owner == account.key())]
+    pub account_token: Account<'info, TokenAccount>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct DecreaseRiskBufferFundPosition<'info> {
+    pub account: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    #[account(mut)]
+    pub account_token: Account<'info, TokenAccount>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct IncreaseRiskBufferFundPositionByRouter<'info> {
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump, seeds::program = ROUTER_PROGRAM_ID)]
+    pub router_authority: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct DecreaseRiskBufferFundPositionByRouter<'info> {
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump, seeds::program = ROUTER_PROGRAM_ID)]
+    pub router_authority: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub receiver_token: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[event]
+pub struct MarketTokenSet {
+    pub pool: Pubkey,
+    pub token: Pubkey,
+}
+
+#[event]
+pub struct MarketConfigChanged {
+    pub pool: Pubkey,
+}
+
+#[event]
+pub struct PositionIncreased {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub side: bool,
+    pub margin_delta: u128,
+    pub size_delta: u128,
+    pub trade_price_x96: u128,
+    pub margin_after: u128,
+    pub size_after: u128,
+}
+
+#[event]
+pub struct PositionDecreased {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub side: bool,
+    pub margin_delta: u128,
+    pub size_delta: u128,
+    pub trade_price_x96: u128,
+    pub margin_after: u128,
+    pub size_after: u128,
+    pub receiver: Pubkey,
+    pub margin_paid: u128,
+}
+
+#[event]
+pub struct PositionLiquidated {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub side: bool,
+    pub decrease_index_price_x96: u128,
+    pub liquidated_size: u128,
+    pub fee_receiver: Pubkey,
+    pub liquidation_execution_fee: u64,
+}
+
+#[event]
+pub struct PositionAutoDeleveraged {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub side: bool,
+    pub size_delta: u128,
+    pub mark_price_x96: u128,
+    pub realized_pnl: u128,
+    pub margin_paid: u128,
+    pub liquidation_fund_after: i128,
+}
+
+#[event]
+pub struct RiskBufferFundPositionIncreased {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub liquidity_delta: u128,
+    pub liquidity_after: u128,
+    pub unlock_time: i64,
+}
+
+#[event]
+pub struct RiskBufferFundPositionDecreased {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub liquidity_delta: u128,
+    pub amount: u128,
+    pub receiver: Pubkey,
+}
+
+#[event]
+pub struct LiquidityPositionIncreased {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub position_id: u64,
+    pub margin_delta: u128,
+    pub liquidity_delta: u128,
+    pub margin_after: u128,
+    pub liquidity_after: u128,
+}
+
+#[event]
+pub struct LiquidityPositionDecreased {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub position_id: u64,
+    pub margin_delta: u128,
+    pub liquidity_delta: u128,
+    pub margin_after: u128,
+    pub liquidity_after: u128,
+    pub receiver: Pubkey,
+    pub margin_paid: u128,
+}
+
+#[event]
+pub struct LiquidityPositionMarginAdjusted {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub position_id: u64,
+    pub margin_delta: i128,
+    pub margin_after: u128,
+    pub receiver: Pubkey,
+}
+
+#[event]
+pub struct LiquidityPositionLiquidated {
+    pub pool: Pubkey,
+    pub account: Pubkey,
+    pub position_id: u64,
+    pub liquidity_delta: u128,
+    pub liquidator: Pubkey,
+    pub fee_receiver: Pubkey,
+    pub liquidation_execution_fee: u64,
+}
+
 
 pub mod liquidity_position_uti {
     use super::*;
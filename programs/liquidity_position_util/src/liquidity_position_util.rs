@@ -1,6 +1,8 @@
 // Import necessary components from the Anchor framework.
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::pubkey::Pubkey;
+use price_utils::math::Q64;
+use crate::position_util::{apply_liquidation_fund_delta, GlobalLiquidityPosition, GlobalPosition, LiquidityPosition, MarketBaseConfig, MarketConfig, MarketPriceConfig, State};
 
 
 
@@ -70,6 +72,8 @@ pub fn increase_liquidity_position(
     parameter: &IncreaseLiquidityPositionContext,
     index: usize 
 ) -> Result<u128> {
+    _settle_liquidity_unrealized_pnl(state, parameter.index_price_x96);
+
     // Retrieve base configuration and targeted liquidity position.
     let base_cfg = &market_config.base_config;
     let position_cache = state.liquidity_positions
@@ -101,7 +105,12 @@ pub fn increase_liquidity_position(
 
     position_cache.margin = margin_after as u128;
     position_cache.liquidity = liquidity_after;
-    position_cache.entry_unrealized_pnl_growth_x64 = global_liquidity_position.entry_unreaized_pnl_growth as i128;
+    position_cache.entry_unrealized_pnl_growth_x64 = global_liquidity_position.unrealized_pnl_growth_x64;
+
+    if parameter.liquidity_delta > 0 {
+        global_liquidity_position.liquidity += parameter.liquidity_delta;
+        _change_price_vertices(state, &market_config.price_config, parameter.market, parameter.index_price_x96);
+    }
 
     Ok(margin_after as u128)
 }
@@ -109,9 +118,12 @@ pub fn increase_liquidity_position(
 /// Decreases a specified liquidity position based on input parameters.
 pub fn decrease_liquidity_position(
     state: &mut State,
+    price_config: &MarketPriceConfig,
     parameter: &DecreaseLiquidityPositionParameter,
     index: usize, 
 ) -> Result<(u128, u128)> {
+    _settle_liquidity_unrealized_pnl(state, parameter.index_price_x96);
+
     // Retrieve targeted liquidity position and validate request.
     let position = state.liquidity_positions
         .get_mut(parameter.account_index)
@@ -132,56 +144,117 @@ pub fn decrease_liquidity_position(
 
     let liquidity_after = position.liquidity - parameter.liquidity_delta;
     if parameter.liquidity_delta > 0 {
-        _decrease_global_liquidity(global_liquidity, &state.globalPosition, parameter.liquidity_delta)?;
+        _decrease_global_liquidity(global_liquidity, &state.global_position, parameter.liquidity_delta)?;
     }
 
     // Update the position with new values.
     position.margin = margin_after as u128;
     position.liquidity = liquidity_after;
+    position.entry_unrealized_pnl_growth_x64 = state.global_liqudity_position.unrealized_pnl_growth_x64;
+
+    if parameter.liquidity_delta > 0 {
+        _change_price_vertices(state, price_config, parameter.market, parameter.index_price_x96);
+    }
 
     Ok((margin_after as u128, parameter.margin_delta))
 }
 
 /// Liquidates a specified liquidity position, applying penalties and adjustments based on market conditions.
+/// Returns the liquidation execution fee and the liquidity removed from the position.
 pub fn liquidate_liquidity_position(
     state: &mut State,
     parameter: &LiquidateLiquidityPositionParameter,
     index: usize,
-    market_cfg: MarketConfig,
-) -> Result<u64> {
+    market_cfg: &MarketConfig,
+) -> Result<(u64, u128)> {
+    _settle_liquidity_unrealized_pnl(state, parameter.index_price_x96);
+
     // Retrieve targeted liquidity position for liquidation.
-    let position = state.liquidity_positions
-        .get_mut(index)
+    let mut position = state.liquidity_positions
+        .get(index)
+        .cloned()
         .ok_or(ErrorCode::LiquidityPositionNotFound)?;
+    require!(position.id == parameter.position_id, ErrorCode::LiquidityPositionNotFound);
 
     let global_liquidity_position = &mut state.global_liqudity_position;
-    let realized_pnl_delta = calculate_realized_pnl(global_liquidity_position, &position).unwrap();
+    let realized_pnl_delta = calculate_realized_pnl(global_liquidity_position, &position)?;
 
     let mut margin_after = position.margin as i128 + realized_pnl_delta;
     // Validate risk rate for potential liquidation.
-    let base_cfg = market_cfg.base_config;
-    validate_liquidity_position_risk_rate(&base_cfg, margin_after, position.liquidity, true)?;
+    let base_cfg = &market_cfg.base_config;
+    validate_liquidity_position_risk_rate(base_cfg, margin_after, position.liquidity, true)?;
+    let liquidation_execution_fee = base_cfg.liquidation_execution_fee;
+
+    // Remove only the liquidity needed to bring the position back under the max leverage if possible.
+    if base_cfg.partial_liquidation_enabled {
+        let liquidity_delta = calculate_partial_liquidity_liquidation_delta(base_cfg, margin_after, position.liquidity)?;
+        if liquidity_delta > 0 && liquidity_delta < position.liquidity {
+            let liquidation_fee = mul_div_up(liquidity_delta, base_cfg.liquidation_fee_rate_per_liquidity_position as u128, 10_000);
+            let margin_left = margin_after - liquidation_execution_fee as i128 - liquidation_fee as i128;
+            let liquidity_left = position.liquidity - liquidity_delta;
+            if validate_liquidity_position_risk_rate(base_cfg, margin_left, liquidity_left, false).is_ok() {
+                _decrease_global_liquidity(global_liquidity_position, &state.global_position, liquidity_delta)?;
+                position.margin = margin_left as u128;
+                position.liquidity = liquidity_left;
+                position.entry_unrealized_pnl_growth_x64 = global_liquidity_position.unrealized_pnl_growth_x64;
+                apply_liquidation_fund_delta(state, parameter.market, liquidation_fee as i128)?;
+                state.liquidity_positions[index] = position;
+                _change_price_vertices(state, &market_cfg.price_config, parameter.market, parameter.index_price_x96);
+                return Ok((liquidation_execution_fee, liquidity_delta));
+            }
+        }
+    }
 
     // Apply global liquidity decrease due to liquidation.
-    _decrease_global_liquidity(global_liquidity_position, &state.globalPosition, position.liquidity)?;
+    _decrease_global_liquidity(global_liquidity_position, &state.global_position, position.liquidity)?;
 
     // Calculate and apply liquidation penalties.
-    let liquidation_execution_fee = base_cfg.liquidation_execution_fee;
     margin_after -= liquidation_execution_fee as i128;
 
-    // Adjust global liquidity and fund based on liquidation outcome.
-    let Q64 = 100; //place holder for constant 
-    if margin_after < 0 {
-        let liquidation_loss = (-margin_after) as u128;
-        // Calculation for adjusted global liquidity based on liquidation.
-        let division_result = mul_div(liquidation_loss, Q64, global_liquidity_position.liquidity);
-        global_liquidity_position.liquidity -= division_result;
-    } else {
-        state.global_liquidation_fund.liquidation_fund += margin_after as i128;
-    }
+    // Credit the remaining margin to the liquidation fund, or run a shortfall through the loss waterfall.
+    apply_liquidation_fund_delta(state, parameter.market, margin_after)?;
 
     // Finalize liquidation by removing the position and returning execution fee.
-    Ok(liquidation_execution_fee)
+    state.liquidity_positions.remove(index);
+    _change_price_vertices(state, &market_cfg.price_config, parameter.market, parameter.index_price_x96);
+    Ok((liquidation_execution_fee, position.liquidity))
+}
+
+/// Marks the LP net position to `index_price_x96` so liquidity changes realize PnL up to the current price.
+pub fn _settle_liquidity_unrealized_pnl(state: &mut State, index_price_x96: u128) {
+    price_utils::settle_liquidity_unrealized_pnl(&mut state.global_liqudity_position, index_price_x96);
+}
+
+/// Rebuilds the price vertices from the global liquidity after it changed.
+pub fn _change_price_vertices(state: &mut State, price_config: &MarketPriceConfig, market: Pubkey, index_price_x96: u128) {
+    price_utils::change_price_vertices(&mut state.price_state, price_config, market, state.global_liqudity_position.liquidity, index_price_x96);
+}
+
+/// Liquidity to remove so that, after paying the liquidation execution fee and the liquidation fee on the
+/// removed liquidity, the rest of the position is back under `max_leverage_per_liquidity_position`.
+/// Returns the whole liquidity when no partial reduction can get it there.
+pub fn calculate_partial_liquidity_liquidation_delta(
+    base_cfg: &MarketBaseConfig,
+    margin: i128,
+    liquidity: u128,
+) -> Result<u128> {
+    let bp: u128 = 10_000;
+    let max_leverage = base_cfg.max_leverage_per_liquidity_position as u128;
+    let margin_left = margin.checked_sub(base_cfg.liquidation_execution_fee as i128).ok_or(ErrorCode::Overflow)?;
+    // every unit removed also costs its liquidation fee, so the reduction only helps while leverage * fee rate < 1
+    let fee_leverage = max_leverage.checked_mul(base_cfg.liquidation_fee_rate_per_liquidity_position as u128).ok_or(ErrorCode::Overflow)?;
+    if margin_left <= 0 || fee_leverage >= bp {
+        return Ok(liquidity);
+    }
+    let max_liquidity = max_leverage.checked_mul(margin_left as u128).ok_or(ErrorCode::Overflow)?;
+    if liquidity <= max_liquidity {
+        return Ok(0);
+    }
+    let liquidity_delta = ceil_div(
+        (liquidity - max_liquidity).checked_mul(bp).ok_or(ErrorCode::Overflow)?,
+        bp.checked_sub(fee_leverage).ok_or(ErrorCode::Underflow)?,
+    );
+    Ok(min(liquidity_delta, liquidity))
 }
 
 // Additional functions for global liquidity management, risk validation, and PnL calculations omitted for brevity.
@@ -216,9 +289,9 @@ pub fn liquidate_liquidity_position(
         liquidatable_position: bool,
     ) -> Result<()> {
         let maintenance_margin = ((liquidity as u128)
-            .checked_mul(base_cfg.liquidation_fee_rate_per_liquidity_position as u128).unwrap()
+            .checked_mul(base_cfg.liquidation_fee_rate_per_liquidity_position as u128).ok_or(ErrorCode::Overflow)?
             / 10_000)  //  add the actual bp from constants when it gets added 
-            .checked_add(base_cfg.liquidation_execution_fee as u128).unwrap();
+            .checked_add(base_cfg.liquidation_execution_fee as u128).ok_or(ErrorCode::Overflow)?;
         
         if !liquidatable_position {
             if margin < 0 || (maintenance_margin as i128) >= margin {
@@ -237,20 +310,20 @@ pub fn liquidate_liquidity_position(
         global_liquidity_position: &GlobalLiquidityPosition,
         position_cache: &LiquidityPosition,
     ) -> Result<i128> {
-        let unrealized_pnl_growth_delta_x64 = global_liquidity_position.entry_unreaized_pnl_growth;
+        let unrealized_pnl_growth_delta_x64 = global_liquidity_position.unrealized_pnl_growth_x64
             - position_cache.entry_unrealized_pnl_growth_x64;
     
         let realized_pnl = if unrealized_pnl_growth_delta_x64 >= 0 {
-            mul_div(
+            price_utils::math::mul_div(
                 unrealized_pnl_growth_delta_x64 as u128,
-                position_cache.liquidity as u128,
-                100, // change it to q64 when constants are added 
+                position_cache.liquidity,
+                Q64,
             ) as i128
         } else {
-            -(mul_div_up(
-                unrealized_pnl_growth_delta_x64 as u128,
-                position_cache.liquidity as u128,
-                100, // Constants::Q64 equivalent
+            -(price_utils::math::mul_div_up(
+                unrealized_pnl_growth_delta_x64.unsigned_abs(),
+                position_cache.liquidity,
+                Q64,
             ) as i128)
         };
     
@@ -262,24 +335,21 @@ pub fn liquidate_liquidity_position(
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct IncreaseLiquidityPositionContext {
-    margin_delta : u128, 
-    liquidity_delta : u128 , 
-    
+    pub market : Pubkey,
+    pub margin_delta : u128,
+    pub liquidity_delta : u128,
+    // Max index price the LP net position is settled and the price vertices are rebuilt at
+    pub index_price_x96 : u128,
 }
 
 pub struct DecreaseLiquidityPositionParameter {
+    pub market: Pubkey,
     pub account_index: usize,
     pub margin_delta: u128,
     pub liquidity_delta: u128,
-    // Include other parameters as needed
+    // Max index price the LP net position is settled and the price vertices are rebuilt at
+    pub index_price_x96: u128,
 }
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct LiquidityPosition {
-    pub margin: u128,
-    pub liquidity: u128,
-    pub entry_unrealized_pnl_growth_x64: i128,
-}
-
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 struct MarketDescriptor {
    
@@ -287,22 +357,6 @@ struct MarketDescriptor {
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 struct PriceFeed {
     
-}
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct GlobalLiquidityPosition {
-    net_size: u128,
-    liquidation_buffer_net_size: u128,
-    liquidity : u128 , 
-    entry_unreaized_pnl_growth : u128 , 
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct PriceState {
-    premium_rate_x96: u128,
-    pending_vertex_index: u8,
-    current_vertex_index: u8,
-    basis_index_price_x96: u128,
-    // Additional fields...
 }
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct UpdatePriceStateParameter {
@@ -330,112 +384,12 @@ pub struct UpdatePriceState {
 
 
 
-#[account]
-pub struct MarketConfig {
-    pub base_config: MarketBaseConfig,
-    pub fee_rate_config: MarketFeeRateConfig,
-    pub price_config: MarketPriceConfig,
-}
-
-#[account]
-pub struct MarketBaseConfig {
-    pub min_margin_per_liquidity_position: u64,
-    pub max_leverage_per_liquidity_position: u32,
-    pub liquidation_fee_rate_per_liquidity_position: u32,
-    pub min_margin_per_position: u64,
-    pub max_leverage_per_position: u32,
-    pub liquidation_fee_rate_per_position: u32,
-    pub max_position_liquidity: u128,
-    pub max_position_value_rate: u32,
-    pub max_size_rate_per_position: u32,
-    pub liquidation_execution_fee: u64,
-    pub interest_rate: u32,
-    pub max_funding_rate: u32,
-}
-
-#[account]
-pub struct MarketFeeRateConfig {
-    pub trading_fee_rate: u32,
-    pub protocol_fee_rate: u32,
-    pub referral_return_fee_rate: u32,
-    pub referral_parent_return_fee_rate: u32,
-    pub referral_discount_rate: u32,
-}
-
-#[account]
-pub struct VertexConfig {
-    pub balance_rate: u32,
-    pub premium_rate: u32,
-}
-
-#[account]
-pub struct MarketPriceConfig {
-    pub max_price_impact_liquidity: u128,
-    pub liquidation_vertex_index: u8,
-    pub vertices: [VertexConfig; 10],
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct State {
-    // This would be stored directly in the State account.
-    pub price_state: PriceState,
-    pub usd_balance: u128,
-    pub protocol_fee: u128,
-    pub liquidity_positions : Vec<LiquidityPosition> , 
-    pub global_liqudity_position : GlobalLiquidityPosition,
-    pub globalPosition : GlobalPosition , 
-    pub global_liquidation_fund : GlobalLiquidationFund,
-    // Referral fees, liquidity positions, positions, and liquidation fund positions
-    // would be managed through PDAs or alternative data structures.
-}
-
-// Assume these structs are defined elsewhere in your module.
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-
-pub struct AccountToLiquidity {
-    account : Pubkey , 
-    liquidity : u128, 
-    margin : u128 , 
-    entry_unreaized_pnl_growth : u128,
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct GlobalPosition {
-    // Fields representing global position...
-    long_size: u128, 
-    short_size : u128 , 
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct PreviousGlobalFundingRate {
-    // Fields...
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct GlobalFundingRateSample {
-    // Fields...
-}
-
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct GlobalLiquidationFund {
-    pub liquidation_fund: i128, // Assuming use of i128 for simplicity.
-    pub liquidity: u128,
-}
-
-pub struct Position {
-    pub margin: u128,
-    pub size: u128,
-    pub entry_price_x96: u128, 
-    pub entry_funding_rate_growth_x96: i128, 
-}
-
-
-
 pub struct  LiquidateLiquidityPositionParameter {
-     market : Pubkey,
-    address : Pubkey,
-    priceFeed : Pubkey, 
-    feeReceiver : Pubkey ,
+    pub market : Pubkey,
+    pub position_id : u64,
+    pub fee_receiver : Pubkey ,
+    // Max index price the LP net position is settled and the price vertices are rebuilt at
+    pub index_price_x96 : u128,
 }
 
 #[error_code]
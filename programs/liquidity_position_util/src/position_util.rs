@@ -1,5 +1,8 @@
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program::pubkey::Pubkey;
+pub use price_utils::{GlobalLiquidityPosition, MarketPriceConfig, PriceState, PriceVertex, VertexConfig};
+use price_utils::math::{Q64, Q96};
+use price_utils::UpdatePriceStateParameter;
 
 
 pub enum Rounding {
@@ -87,6 +90,8 @@ pub struct IncreasePositionParameter {
     pub side: bool,
     pub margin_delta: u128,
     pub size_delta: u128,
+    // Index price the trade price is taken from, max for longs and min for shorts
+    pub index_price_x96: u128,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -97,6 +102,8 @@ pub struct DecreasePositionParameter {
     pub margin_delta: u128,
     pub size_delta: u128,
     pub receiver: Pubkey,
+    // Index price the trade price is taken from, min for longs and max for shorts
+    pub index_price_x96: u128,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -123,7 +130,6 @@ pub struct LiquidateParameter {
     pub market: Pubkey,
     pub account: Pubkey,
     pub side: bool,
-    pub trade_price_x96: u128,
     pub decrease_index_price_x96: u128,
     pub required_funding_fee: i128, // Adjusted to i128
     pub fee_receiver: Pubkey,
@@ -139,12 +145,14 @@ pub struct DistributeFeeParameter {
     pub liquidation_fee: i128, // Adjusted to i128
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct MarketConfig {
     pub base_config: MarketBaseConfig,
     pub fee_rate_config: MarketFeeRateConfig,
     pub price_config: MarketPriceConfig,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct MarketBaseConfig {
     pub min_margin_per_liquidity_position: u64,
     pub max_leverage_per_liquidity_position: u32,
@@ -158,8 +166,17 @@ pub struct MarketBaseConfig {
     pub liquidation_execution_fee: u64,
     pub interest_rate: u32,
     pub max_funding_rate: u32,
+    // Liquidates only the slice of a position, or the liquidity of a liquidity position, needed to restore its margin when enabled
+    pub partial_liquidation_enabled: bool,
+    // Buffer above the maintenance margin, in basis points, a partially liquidated position is left with
+    pub partial_liquidation_buffer_rate: u32,
+    // Liquidation fund deficit beyond which executors may auto-deleverage positions
+    pub adl_deficit_threshold: u128,
+    // Share, in basis points, of liquidation gains credited to the risk buffer fund
+    pub risk_buffer_fund_gain_rate: u32,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct MarketFeeRateConfig {
     pub trading_fee_rate: u32,
     pub protocol_fee_rate: u32,
@@ -168,41 +185,18 @@ pub struct MarketFeeRateConfig {
     pub referral_discount_rate: u32,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct VertexConfig {
-    pub balance_rate: u32,
-    pub premium_rate: u32,
-}
-
-pub struct MarketPriceConfig {
-    pub max_price_impact_liquidity: u128,
-    pub liquidation_vertex_index: u8,
-    pub vertices: [VertexConfig; 10],
-}
-
-pub struct GlobalLiquidityPosition {
-    pub net_size: u128,
-    pub liquidation_buffer_net_size: u128,
-    pub max_size : u128,
-    // Assuming previousSPPriceX96 can be represented as u128 for simplicity,
-    // but you may need to handle fixed-point arithmetic separately.
-    pub previous_sp_price_x96: u128,
-    pub side: bool,
-    pub liquidity: u128,
-    // Assuming unrealizedPnLGrowthX64 can be represented as i128 for simplicity,
-    // actual fixed-point operations need to consider the scale factor.
-    pub unrealized_pnl_growth_x64: i128,
-}
-
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct LiquidityPosition {
+    pub id: u64,
+    pub account: Pubkey,
     pub margin: u128,
     pub liquidity: u128,
     pub entry_unrealized_pnl_growth_x64: i128,
 }
 
 
-
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct GlobalPosition {
     pub long_size: u128,
     pub short_size: u128,
@@ -225,8 +219,10 @@ pub struct GlobalFundingRateSample {
     pub cumulative_premium_rate_x96: i128, 
 }
 
-
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct Position {
+    pub account: Pubkey,
+    pub side: bool,
     pub margin: u128,
     pub size: u128,
     pub entry_price_x96: u128, 
@@ -236,33 +232,182 @@ fn calculate_trading_fee(size_delta: u128, trade_price_x96: u128, trading_fee_ra
     // Placeholder for trading fee calculation
     0
 }
+#[account]
 pub struct State {
    
     pub price_state: PriceState,
     pub usd_balance: u128,
     pub protocol_fee: u128,
-    pub liquidity_positions : Vec<AccountToLiquidity> , 
+    pub liquidity_positions : Vec<LiquidityPosition> , 
     pub global_liqudity_position : GlobalLiquidityPosition,
     pub global_position : GlobalPosition,
-    pub positions : Vec<Position> 
+    pub global_liquidation_fund : GlobalLiquidationFund,
+    pub global_risk_buffer_fund : GlobalRiskBufferFund,
+    pub risk_buffer_fund_positions : Vec<RiskBufferFundPosition>,
+    pub positions : Vec<Position> ,
+    pub market_config : MarketConfig,
+    // index token the market is priced in, set once by the governor
+    pub token : Pubkey,
+}
+
+/// Returns the index of the `account`'s position on `side`, if it has one.
+pub fn get_position_index(state: &State, account: Pubkey, side: bool) -> Option<usize> {
+    state.positions.iter().position(|position| position.account == account && position.side == side)
+}
+
+/// Returns the index of the liquidity position with `id`, if it exists.
+pub fn get_liquidity_position_index(state: &State, id: u64) -> Option<usize> {
+    state.liquidity_positions.iter().position(|position| position.id == id)
 }
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct GlobalLiquidationFund {
+    pub liquidation_fund: i128,
+    pub liquidity: u128,
+}
 
-pub struct AccountToLiquidity {
-    account : Pubkey , 
-    liquidity : u128, 
-    margin : u128 , 
-    entry_unreaized_pnl_growth : u128,
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct GlobalRiskBufferFund {
+    pub risk_buffer_fund: i128,
+    pub liquidity: u128,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
-pub struct PriceState {
-    premium_rate_x96: u128,
-    pending_vertex_index: u8,
-    current_vertex_index: u8,
-    basis_index_price_x96: u128,
-    // Additional fields...
+pub struct RiskBufferFundPosition {
+    pub account: Pubkey,
+    pub liquidity: u128,
+    pub unlock_time: i64,
 }
+
+/// Time a risk buffer fund position stays locked after each increase
+pub const RISK_BUFFER_FUND_LOCK_PERIOD: i64 = 90 * 24 * 60 * 60;
+
+/// Returns the index of the `account`'s risk buffer fund position, if it has one.
+pub fn get_risk_buffer_fund_position_index(state: &State, account: Pubkey) -> Option<usize> {
+    state.risk_buffer_fund_positions.iter().position(|position| position.account == account)
+}
+
+/// Adds `liquidity_delta` to the `account`'s risk buffer fund position and restarts its lock-up.
+/// Returns the position's liquidity and unlock time after the increase.
+pub fn increase_risk_buffer_fund_position(state: &mut State, account: Pubkey, liquidity_delta: u128, now: i64) -> Result<(u128, i64)> {
+    require!(liquidity_delta > 0, ErrorCode::InsufficientRiskBufferFundLiquidity);
+    let fund = &mut state.global_risk_buffer_fund;
+    fund.risk_buffer_fund = fund.risk_buffer_fund.checked_add(liquidity_delta as i128).ok_or(ErrorCode::Overflow)?;
+    fund.liquidity = fund.liquidity.checked_add(liquidity_delta).ok_or(ErrorCode::Overflow)?;
+
+    let unlock_time = now.checked_add(RISK_BUFFER_FUND_LOCK_PERIOD).ok_or(ErrorCode::Overflow)?;
+    let index = match get_risk_buffer_fund_position_index(state, account) {
+        Some(index) => index,
+        None => {
+            state.risk_buffer_fund_positions.push(RiskBufferFundPosition { account, liquidity: 0, unlock_time });
+            state.risk_buffer_fund_positions.len() - 1
+        }
+    };
+    let position = &mut state.risk_buffer_fund_positions[index];
+    position.liquidity = position.liquidity.checked_add(liquidity_delta).ok_or(ErrorCode::Overflow)?;
+    position.unlock_time = unlock_time;
+    Ok((position.liquidity, unlock_time))
+}
+
+/// Removes `liquidity_delta` from the `account`'s unlocked risk buffer fund position. The position redeems its
+/// pro-rata share of the fund, so liquidation gains and losses absorbed by the fund are shared across positions.
+/// Returns the amount to pay out.
+pub fn decrease_risk_buffer_fund_position(state: &mut State, account: Pubkey, liquidity_delta: u128, now: i64) -> Result<u128> {
+    let index = get_risk_buffer_fund_position_index(state, account).ok_or(ErrorCode::PositionNotFound)?;
+    let position = &state.risk_buffer_fund_positions[index];
+    require!(now >= position.unlock_time, ErrorCode::RiskBufferFundPositionLocked);
+    require!(liquidity_delta > 0 && liquidity_delta <= position.liquidity, ErrorCode::InsufficientRiskBufferFundLiquidity);
+
+    let fund = &mut state.global_risk_buffer_fund;
+    let amount = mul_div(liquidity_delta, fund.risk_buffer_fund.max(0) as u128, fund.liquidity);
+    fund.risk_buffer_fund -= amount as i128;
+    fund.liquidity -= liquidity_delta;
+
+    let position = &mut state.risk_buffer_fund_positions[index];
+    position.liquidity -= liquidity_delta;
+    if position.liquidity == 0 {
+        state.risk_buffer_fund_positions.remove(index);
+    }
+    Ok(amount)
+}
+
+/// Where a slice of bad debt was absorbed, in waterfall order.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BadDebtSource {
+    LiquidationFund,
+    RiskBufferFund,
+    LiquidityProviders,
+}
+
+#[event]
+pub struct BadDebtAbsorbed {
+    pub market: Pubkey,
+    pub source: BadDebtSource,
+    pub amount: u128,
+    pub remaining: u128,
+}
+
+/// Credits a positive liquidation result to the liquidation fund, less the risk buffer fund's share of it,
+/// or runs a negative one through the loss waterfall.
+pub fn apply_liquidation_fund_delta(state: &mut State, market: Pubkey, liquidation_fund_delta: i128) -> Result<()> {
+    if liquidation_fund_delta >= 0 {
+        let risk_buffer_fund_share = if state.global_risk_buffer_fund.liquidity > 0 {
+            mul_div(liquidation_fund_delta as u128, state.market_config.base_config.risk_buffer_fund_gain_rate as u128, 10_000) as i128
+        } else {
+            0
+        };
+        state.global_risk_buffer_fund.risk_buffer_fund = state.global_risk_buffer_fund.risk_buffer_fund
+            .checked_add(risk_buffer_fund_share)
+            .ok_or(ErrorCode::Overflow)?;
+        state.global_liquidation_fund.liquidation_fund = state.global_liquidation_fund.liquidation_fund
+            .checked_add(liquidation_fund_delta - risk_buffer_fund_share)
+            .ok_or(ErrorCode::Overflow)?;
+        return Ok(());
+    }
+    absorb_bad_debt(state, market, liquidation_fund_delta.unsigned_abs())
+}
+
+/// Loss waterfall shared by every liquidation path: bad debt is absorbed by the liquidation fund, then the
+/// risk buffer fund, and only then socialized over the liquidity providers' unrealized PnL growth.
+/// Whatever is left when there is no liquidity to socialize over stays as a liquidation fund deficit.
+pub fn absorb_bad_debt(state: &mut State, market: Pubkey, loss: u128) -> Result<()> {
+    let mut remaining = loss;
+
+    let liquidation_fund = &mut state.global_liquidation_fund.liquidation_fund;
+    let amount = min((*liquidation_fund).max(0) as u128, remaining);
+    *liquidation_fund -= amount as i128;
+    remaining -= amount;
+    _emit_bad_debt_absorbed(market, BadDebtSource::LiquidationFund, amount, remaining);
+
+    let risk_buffer_fund = &mut state.global_risk_buffer_fund.risk_buffer_fund;
+    let amount = min((*risk_buffer_fund).max(0) as u128, remaining);
+    *risk_buffer_fund -= amount as i128;
+    remaining -= amount;
+    _emit_bad_debt_absorbed(market, BadDebtSource::RiskBufferFund, amount, remaining);
+
+    let global_liquidity_position = &mut state.global_liqudity_position;
+    if remaining > 0 && global_liquidity_position.liquidity > 0 {
+        let unrealized_pnl_growth_delta_x64 = price_utils::math::mul_div_up(remaining, Q64, global_liquidity_position.liquidity);
+        global_liquidity_position.unrealized_pnl_growth_x64 = global_liquidity_position.unrealized_pnl_growth_x64
+            .checked_sub(unrealized_pnl_growth_delta_x64 as i128)
+            .ok_or(ErrorCode::Overflow)?;
+        let amount = remaining;
+        remaining = 0;
+        _emit_bad_debt_absorbed(market, BadDebtSource::LiquidityProviders, amount, remaining);
+    }
+
+    if remaining > 0 {
+        state.global_liquidation_fund.liquidation_fund -= remaining as i128;
+    }
+    Ok(())
+}
+
+fn _emit_bad_debt_absorbed(market: Pubkey, source: BadDebtSource, amount: u128, remaining: u128) {
+    if amount > 0 {
+        emit!(BadDebtAbsorbed { market, source, amount, remaining });
+    }
+}
+
 pub fn change_max_size(
     global_liquidity_position: &GlobalLiquidityPosition,
     base_cfg: &MarketBaseConfig,
@@ -316,11 +461,20 @@ pub fn increase_position(
             parameter.size_delta,
         )?;
 
-        // Placeholder for index price calculation
-        let index_price_x96 = 0u128; // Market Util call 
-
-        // Placeholder for trade price calculation and updating price state
-        trade_price_x96 = index_price_x96; 
+        // moving the price state settles the LP net position at the trade price
+        trade_price_x96 = price_utils::update_price_state(
+            &mut state.global_liqudity_position,
+            &mut state.price_state,
+            &market_config.price_config,
+            &UpdatePriceStateParameter {
+                market: parameter.market,
+                side: parameter.side,
+                size_delta: parameter.size_delta,
+                index_price_x96: parameter.index_price_x96,
+                liquidation_vertex_index: market_config.price_config.liquidation_vertex_index,
+                liquidation: false,
+            },
+        )?;
 
         // Assuming a function to distribute fees
         let fee_param = &DistributeFeeParameter {
@@ -331,7 +485,7 @@ pub fn increase_position(
             trading_fee_state: trading_fee_state.clone(),
             liquidation_fee: 0,
         };
-        let trading_fee = distribute_fee(
+        trading_fee = distribute_fee(
             &mut state.global_liqudity_position,
             &market_config.fee_rate_config , 
             fee_param
@@ -357,32 +511,35 @@ pub fn increase_position(
         trade_price_x96,
     );
 
+    validate_position_margin(base_cfg, margin_after, size_after, entry_price_after_x96)?;
+
     let maintain_parameter: MaintainMarginRateParameter = MaintainMarginRateParameter{
         margin : margin_after , 
         side : parameter.side ,
         size : size_after , 
         entry_price_x96 : entry_price_after_x96 , 
-        decrease_price_x96 : 0, // place holder to add market util 
+        decrease_price_x96 : parameter.index_price_x96,
         trading_fee_rate : trading_fee_state.trading_fee_rate , 
         liquidatable_position : false , 
 
     };
-    validate_position_liquidate_maintain_margin_rate(base_cfg, &maintain_parameter );
+    validate_position_liquidate_maintain_margin_rate(base_cfg, &maintain_parameter)?;
 
     if parameter.size_delta > 0 {
         // market util call 
         increase_global_position(&mut state.global_position, parameter.side , parameter.size_delta);
     }
 
-    let position: &mut Position = state.positions.get_mut(index).unwrap();
-    position.margin = margin_after as u128;
-    position.size = size_after;
+    position_cache.margin = margin_after as u128;
+    position_cache.size = size_after;
     position_cache.entry_price_x96 = entry_price_after_x96;
-    position_cache.entry_funding_rate_growth_x96 = 0; // Placeholder for actual update
+    position_cache.entry_funding_rate_growth_x96 = global_funding_growth;
+    *state.positions.get_mut(index).ok_or(ErrorCode::PositionNotFound)? = position_cache.clone();
 
 
     Ok(trade_price_x96) 
 }
+/// Decreases `position` in place and returns the trade price and the margin to pay out to the receiver.
 pub fn decrease_position(
     state: &mut State,
     market_config: &MarketConfig,
@@ -400,40 +557,50 @@ pub fn decrease_position(
         return Err(error!(ErrorCode::InsufficientSizeToDecrease));
     }
 
-    // Placeholder for settle liquidity unrealized PnL and decrease index price calculation
-
-    let mut trading_fee_state = build_trading_fee_state(&market_config.fee_rate_config, parameter.account, 0, 0); // Placeholder for referral tokens
-    let mut trade_price_x96 = 100;
+    let trading_fee_state = build_trading_fee_state(&market_config.fee_rate_config, parameter.account, 0, 0); // Placeholder for referral tokens
     let global_funding_rate_growth_x96 = choose_previous_global_funding_rate_growth_x96(&state.global_position, parameter.side);
-    let mut trading_fee = 100;
-    let mut funding_fee  = 100;
-    let mut realized_pnl_delta = 100;
+    let funding_fee = calculate_funding_fee(
+        global_funding_rate_growth_x96,
+        position.entry_funding_rate_growth_x96,
+        position.size,
+    );
+    let mut trade_price_x96 = 0;
+    let mut trading_fee = 0;
+    let mut realized_pnl_delta = 0;
     if parameter.size_delta > 0 {
-    trade_price_x96 = 100; // place holder for the PriceUtil call 
+    // closing trades against the position's side, settling the LP net position at the trade price
+    trade_price_x96 = price_utils::update_price_state(
+        &mut state.global_liqudity_position,
+        &mut state.price_state,
+        &market_config.price_config,
+        &UpdatePriceStateParameter {
+            market: parameter.market,
+            side: !parameter.side,
+            size_delta: parameter.size_delta,
+            index_price_x96: parameter.index_price_x96,
+            liquidation_vertex_index: market_config.price_config.liquidation_vertex_index,
+            liquidation: false,
+        },
+    )?;
     let fee_param = &DistributeFeeParameter {
         market: parameter.market,
         account: parameter.account,
-        size_delta: position.size,
+        size_delta: parameter.size_delta,
         trade_price_x96: trade_price_x96,
         trading_fee_state: trading_fee_state.clone(),
         liquidation_fee: 0,
     };
-    let trading_fee = distribute_fee(
+    trading_fee = distribute_fee(
         &mut state.global_liqudity_position,
         &market_config.fee_rate_config , 
         fee_param
-    );
-     funding_fee = calculate_funding_fee(
-        global_funding_rate_growth_x96,
-        position.entry_funding_rate_growth_x96,
-        position.size,
     );
     // Calculate unrealized PnL delta based on the size decrease
     realized_pnl_delta = calculate_unrealized_pnl(
         parameter.side,
         parameter.size_delta,
         position.entry_price_x96,
-        0, // Placeholder for current price, assuming this will be calculated or provided
+        trade_price_x96,
     );
     }
     let margin_after = (position.margin as i128) + realized_pnl_delta + funding_fee - (trading_fee as i128) - (parameter.margin_delta as i128);
@@ -442,27 +609,21 @@ pub fn decrease_position(
     }
 
     let size_after = position.size - parameter.size_delta;
-
-    // Update position's size and margin
- 
-
-    if size_after > 0 {
-        // calls to marketutil and other libraries 
-    }
-
-    else {
-        // Logic to delete the position or mark it as closed
+    if size_after > 0 && parameter.margin_delta > 0 {
+        validate_position_margin(&market_config.base_config, margin_after, size_after, position.entry_price_x96)?;
     }
 
     // Adjust global position
     if parameter.size_delta > 0 {
     decrease_global_position(&mut state.global_position, parameter.side, parameter.size_delta);
     }
+    // closing the whole position releases the margin left on it as well
+    let margin_paid = if size_after == 0 { parameter.margin_delta + margin_after as u128 } else { parameter.margin_delta };
     position.size = size_after;
-    position.margin = margin_after as u128;
+    position.margin = if size_after == 0 { 0 } else { margin_after as u128 };
     position.entry_funding_rate_growth_x96 = global_funding_rate_growth_x96;
     
-    Ok((0, margin_after as u128)) // placeholder values 
+    Ok((trade_price_x96, margin_paid))
 }
 
 pub fn liquidate_position(
@@ -471,10 +632,11 @@ pub fn liquidate_position(
     position: &mut Position, 
     trading_fee_state: &TradingFeeState,
     parameter: &LiquidateParameter,
-) -> Result<()> {
+) -> Result<u128> {
     if position.size == 0 {
         return err!(ErrorCode::PositionNotFound);
     }
+    price_utils::settle_liquidity_unrealized_pnl(&mut state.global_liqudity_position, parameter.decrease_index_price_x96);
     let base_cfg = &market_config.base_config;
     let liquidation_execution_fee = base_cfg.liquidation_execution_fee;
     let liquidation_fee_rate = base_cfg.liquidation_fee_rate_per_position;
@@ -489,6 +651,29 @@ pub fn liquidate_position(
         liquidation_execution_fee,
     );
 
+    if base_cfg.partial_liquidation_enabled {
+        let equity = (position.margin as i128)
+            .checked_add(parameter.required_funding_fee)
+            .and_then(|margin| margin.checked_add(calculate_unrealized_pnl(parameter.side, position.size, position.entry_price_x96, parameter.decrease_index_price_x96)))
+            .ok_or(ErrorCode::Overflow)?;
+        let size_delta = calculate_partial_liquidation_size(
+            base_cfg,
+            position.size,
+            position.entry_price_x96,
+            parameter.decrease_index_price_x96,
+            trading_fee_state.trading_fee_rate,
+            equity,
+        )?;
+        if size_delta > 0 && size_delta < position.size {
+            if let Some(margin_after) = _partially_liquidate_position(state, market_config, position, trading_fee_state, parameter, liquidation_price_x96, size_delta)? {
+                position.size -= size_delta;
+                position.margin = margin_after;
+                position.entry_funding_rate_growth_x96 = choose_previous_global_funding_rate_growth_x96(&state.global_position, parameter.side);
+                return Ok(size_delta);
+            }
+        }
+    }
+
     let liquidation_fee = calculate_liquidation_fee(
         position.size,
         position.entry_price_x96,
@@ -508,11 +693,12 @@ pub fn liquidate_position(
     }
 
     // Calculate the difference if the liquidation price differs from the trade price
+    let trade_price_x96 = _update_price_state_by_liquidation(state, market_config, parameter, position.size)?;
     liquidation_fund_delta += calculate_unrealized_pnl(
         parameter.side,
         position.size,
         liquidation_price_x96,
-        parameter.trade_price_x96,
+        trade_price_x96,
     );
 
 
@@ -529,15 +715,114 @@ pub fn liquidate_position(
         &market_config.fee_rate_config , 
         fee_param
     );
+    apply_liquidation_fund_delta(state, parameter.market, liquidation_fund_delta)?;
 
     // Decrease the global position
     decrease_global_position(&mut state.global_position, parameter.side, position.size);
 
+    // the caller deletes the emptied position
+    let size_delta = position.size;
+    position.size = 0;
+    Ok(size_delta)
+}
 
-    // add delete the position 
-    Ok(())
+/// Closes `size_delta` of the position at the liquidation price, returning the margin left on the rest of it.
+/// Returns `None` when that margin would not stay positive, in which case the whole position is liquidated.
+fn _partially_liquidate_position(
+    state: &mut State,
+    market_config: &MarketConfig,
+    position: &Position,
+    trading_fee_state: &TradingFeeState,
+    parameter: &LiquidateParameter,
+    liquidation_price_x96: u128,
+    size_delta: u128,
+) -> Result<Option<u128>> {
+    let liquidation_fee = calculate_liquidation_fee(size_delta, position.entry_price_x96, market_config.base_config.liquidation_fee_rate_per_position);
+    let trading_fee = calculate_trading_fee(size_delta, liquidation_price_x96, trading_fee_state.trading_fee_rate);
+    let realized_pnl = calculate_unrealized_pnl(parameter.side, size_delta, position.entry_price_x96, liquidation_price_x96);
+    let margin_after = (position.margin as i128)
+        .checked_add(parameter.required_funding_fee)
+        .and_then(|margin| margin.checked_add(realized_pnl))
+        .and_then(|margin| margin.checked_sub(liquidation_fee as i128))
+        .and_then(|margin| margin.checked_sub(trading_fee as i128))
+        .and_then(|margin| margin.checked_sub(market_config.base_config.liquidation_execution_fee as i128))
+        .ok_or(ErrorCode::Overflow)?;
+    if margin_after <= 0 {
+        return Ok(None);
+    }
+
+    // the liquidation fund keeps the fee and the difference between the liquidation and trade price of the slice
+    let trade_price_x96 = _update_price_state_by_liquidation(state, market_config, parameter, size_delta)?;
+    let liquidation_fund_delta = liquidation_fee as i128 + calculate_unrealized_pnl(
+        parameter.side,
+        size_delta,
+        liquidation_price_x96,
+        trade_price_x96,
+    );
+    let fee_param = &DistributeFeeParameter {
+        market: parameter.market,
+        account: parameter.account,
+        size_delta,
+        trade_price_x96: liquidation_price_x96,
+        trading_fee_state: trading_fee_state.clone(),
+        liquidation_fee: liquidation_fund_delta,
+    };
+    distribute_fee(&mut state.global_liqudity_position, &market_config.fee_rate_config, fee_param);
+    apply_liquidation_fund_delta(state, parameter.market, liquidation_fund_delta)?;
+    decrease_global_position(&mut state.global_position, parameter.side, size_delta);
+
+    Ok(Some(margin_after as u128))
 }
 
+// Trades the liquidated `size_delta` against the LPs, moving the price state and their net position. Returns the trade price.
+fn _update_price_state_by_liquidation(state: &mut State, market_config: &MarketConfig, parameter: &LiquidateParameter, size_delta: u128) -> Result<u128> {
+    price_utils::update_price_state(
+        &mut state.global_liqudity_position,
+        &mut state.price_state,
+        &market_config.price_config,
+        &UpdatePriceStateParameter {
+            market: parameter.market,
+            side: !parameter.side,
+            size_delta,
+            index_price_x96: parameter.decrease_index_price_x96,
+            liquidation_vertex_index: market_config.price_config.liquidation_vertex_index,
+            liquidation: true,
+        },
+    )
+}
+
+/// Minimum size to close so that the rest of the position holds its maintenance margin plus
+/// `partial_liquidation_buffer_rate`, given the position's `equity` (margin, funding and unrealized PnL).
+/// Returns the whole size when partial liquidation is disabled or no slice can restore the buffer.
+pub fn calculate_partial_liquidation_size(
+    base_cfg: &MarketBaseConfig,
+    size: u128,
+    entry_price_x96: u128,
+    index_price_x96: u128,
+    trading_fee_rate: u32,
+    equity: i128,
+) -> Result<u128> {
+    let buffer_rate = base_cfg.partial_liquidation_buffer_rate as u128;
+    if !base_cfg.partial_liquidation_enabled || buffer_rate == 0 || equity <= 0 {
+        return Ok(size);
+    }
+    // closing a unit of size costs the same fees the maintenance margin reserves for it
+    let maintenance_margin = calculate_maintenance_margin(size, entry_price_x96, index_price_x96, base_cfg.liquidation_fee_rate_per_position, trading_fee_rate, 0);
+    if maintenance_margin == 0 {
+        return Ok(size);
+    }
+    let bp: u128 = 10_000;
+    let required = maintenance_margin
+        .checked_add(base_cfg.liquidation_execution_fee as u128)
+        .and_then(|margin| margin.checked_mul(bp + buffer_rate))
+        .ok_or(ErrorCode::Overflow)?;
+    let shortfall = required.saturating_sub((equity as u128).checked_mul(bp).ok_or(ErrorCode::Overflow)?);
+    let size_delta = ceil_div(
+        shortfall.checked_mul(size).ok_or(ErrorCode::Overflow)?,
+        maintenance_margin.checked_mul(buffer_rate).ok_or(ErrorCode::Overflow)?,
+    );
+    Ok(min(size_delta, size))
+}
 
 pub fn calculate_liquidation_price_x96(
     position: &Position,
@@ -664,9 +949,7 @@ pub fn calculate_next_entry_price_x96(
 
 /// Calculate the liquidity (value) of a position.
 pub fn calculate_liquidity(size: u128, price_x96: u128) -> u128 {
-    let q96   = 100 ; // change this too 
-
-    size.checked_mul(price_x96).unwrap().checked_div(q96).unwrap()
+    price_utils::math::mul_div(size, price_x96, Q96)
 }
 
 /// Calculate the unrealized PnL of a position based on entry price.
@@ -844,7 +1127,7 @@ pub fn validate_increase_size(
 }
 
 
-fn build_trading_fee_state(fee_rate_cfg: &MarketFeeRateConfig, account: Pubkey , referral_token : u128 , referral_parent_token : u128) -> TradingFeeState {
+pub fn build_trading_fee_state(fee_rate_cfg: &MarketFeeRateConfig, account: Pubkey , referral_token : u128 , referral_parent_token : u128) -> TradingFeeState {
     
    
     let trading_fee_rate = if referral_token == 0 {
@@ -872,6 +1155,21 @@ fn adjust_global_funding_rate(global_position: &mut GlobalPosition, long_rate_ad
     // Additional logic as needed...
 }
 
+/// Validates that a position keeps at least `min_margin_per_position` of margin and that its notional at
+/// the entry price is no more than `max_leverage_per_position` times the margin.
+/// The errors carry the offending value and the limit it broke.
+pub fn validate_position_margin(base_cfg: &MarketBaseConfig, margin: i128, size: u128, entry_price_x96: u128) -> Result<()> {
+    if margin < base_cfg.min_margin_per_position as i128 {
+        return Err(error!(ErrorCode::MarginBelowMinimum).with_values((margin, base_cfg.min_margin_per_position)));
+    }
+    let notional = calculate_liquidity(size, entry_price_x96);
+    let max_notional = (margin as u128).checked_mul(base_cfg.max_leverage_per_position as u128).ok_or(ErrorCode::Overflow)?;
+    if notional > max_notional {
+        return Err(error!(ErrorCode::LeverageTooHigh).with_values((notional, max_notional)));
+    }
+    Ok(())
+}
+
 pub fn validate_global_liquidity(global_liquidity: u128) -> Result<()> {
     if global_liquidity == 0 {
         // Replace `InsufficientGlobalLiquidity` with the actual error handling approach you prefer.
@@ -929,6 +1227,85 @@ pub fn adjust_funding_rate_by_liquidation(
     liquidation_fund_loss
 }
 
+/// Returns whether the liquidation fund deficit is beyond the auto-deleveraging threshold.
+pub fn is_adl_required(state: &State, base_cfg: &MarketBaseConfig) -> bool {
+    let deficit = -state.global_liquidation_fund.liquidation_fund;
+    deficit > 0 && deficit as u128 > base_cfg.adl_deficit_threshold
+}
+
+/// Returns the index of the position auto-deleveraging reduces first: among the profitable positions
+/// opposing the liquidity providers, the one with the highest unrealized PnL rate times leverage.
+pub fn select_adl_position(state: &State, mark_price_x96: u128) -> Option<usize> {
+    let lp_side = state.global_liqudity_position.side;
+    let has_net_position = state.global_liqudity_position.net_size > 0;
+    state.positions.iter()
+        .enumerate()
+        .filter(|(_, position)| position.size > 0 && position.margin > 0 && (!has_net_position || position.side != lp_side))
+        .filter_map(|(index, position)| {
+            let unrealized_pnl = calculate_unrealized_pnl(position.side, position.size, position.entry_price_x96, mark_price_x96);
+            if unrealized_pnl <= 0 {
+                return None;
+            }
+            let notional = calculate_liquidity(position.size, mark_price_x96);
+            let score = (unrealized_pnl as u128).saturating_mul(notional) / position.margin / position.margin;
+            Some((score, index))
+        })
+        .max()
+        .map(|(_, index)| index)
+}
+
+/// Force-reduces the position at `index` at the mark price until its realized profit covers the liquidation
+/// fund deficit. The profit of the reduced slice goes to the fund up to the deficit; the rest of it, the
+/// funding fee and the slice's share of the margin are settled to the owner. The reduced size closes against
+/// the liquidity providers through the price state like any decrease.
+/// Returns the size reduced, the profit credited to the fund and the margin to pay the owner.
+pub fn auto_deleverage_position(state: &mut State, market_config: &MarketConfig, market: Pubkey, index: usize, mark_price_x96: u128) -> Result<(u128, u128, u128)> {
+    let deficit = (-state.global_liquidation_fund.liquidation_fund).max(0) as u128;
+    let position = state.positions.get(index).ok_or(ErrorCode::PositionNotFound)?;
+    let unrealized_pnl = calculate_unrealized_pnl(position.side, position.size, position.entry_price_x96, mark_price_x96);
+    if deficit == 0 || unrealized_pnl <= 0 {
+        return err!(ErrorCode::AdlNotRequired);
+    }
+    let side = position.side;
+    let size_delta = min(ceil_div(deficit.checked_mul(position.size).ok_or(ErrorCode::Overflow)?, unrealized_pnl as u128), position.size);
+    let slice_pnl = mul_div(unrealized_pnl as u128, size_delta, position.size);
+    let realized_pnl = min(slice_pnl, deficit);
+
+    let global_funding_rate_growth_x96 = choose_previous_global_funding_rate_growth_x96(&state.global_position, side);
+    let funding_fee = calculate_funding_fee(global_funding_rate_growth_x96, position.entry_funding_rate_growth_x96, position.size);
+    let margin_after = (position.margin as i128)
+        .checked_add(funding_fee)
+        .and_then(|margin| margin.checked_add((slice_pnl - realized_pnl) as i128))
+        .ok_or(ErrorCode::Overflow)?
+        .max(0) as u128;
+    let size_after = position.size - size_delta;
+    let payout = if size_after == 0 { margin_after } else { mul_div(margin_after, size_delta, position.size) };
+
+    price_utils::update_price_state(
+        &mut state.global_liqudity_position,
+        &mut state.price_state,
+        &market_config.price_config,
+        &UpdatePriceStateParameter {
+            market,
+            side: !side,
+            size_delta,
+            index_price_x96: mark_price_x96,
+            liquidation_vertex_index: market_config.price_config.liquidation_vertex_index,
+            liquidation: false,
+        },
+    )?;
+    decrease_global_position(&mut state.global_position, side, size_delta);
+    state.global_liquidation_fund.liquidation_fund += realized_pnl as i128;
+    let position = &mut state.positions[index];
+    position.size = size_after;
+    position.margin = margin_after - payout;
+    position.entry_funding_rate_growth_x96 = global_funding_rate_growth_x96;
+    if position.size == 0 {
+        state.positions.remove(index);
+    }
+    Ok((size_delta, realized_pnl, payout))
+}
+
 pub fn validate_position_liquidate_maintain_margin_rate(base_cfg: &MarketBaseConfig, parameter: &MaintainMarginRateParameter) -> Result<()> {
     let unrealized_pnl = calculate_unrealized_pnl(parameter.side, parameter.size, parameter.entry_price_x96, parameter.decrease_price_x96);
     let maintenance_margin = calculate_maintenance_margin(parameter.size, parameter.entry_price_x96, parameter.decrease_price_x96, base_cfg.liquidation_fee_rate_per_position, parameter.trading_fee_rate, base_cfg.liquidation_execution_fee);
@@ -965,9 +1342,113 @@ pub enum ErrorCode {
     InsufficientSizeToDecrease , 
     #[msg("InsufficientMargin")]
     InsufficientMargin,
+    #[msg("Margin is below min_margin_per_position")]
+    MarginBelowMinimum,
+    #[msg("Leverage exceeds max_leverage_per_position")]
+    LeverageTooHigh,
     #[msg("Invalid Position")]
     PositionNotFound ,
+    #[msg("Invalid fee receiver")]
+    InvalidFeeReceiver,
+    #[msg("Auto-deleveraging not required")]
+    AdlNotRequired,
+    #[msg("Position is not the top ranked for auto-deleveraging")]
+    AdlPositionNotSelected,
+    #[msg("Risk buffer fund position is locked")]
+    RiskBufferFundPositionLocked,
+    #[msg("Insufficient risk buffer fund liquidity")]
+    InsufficientRiskBufferFundLiquidity,
+    #[msg("Market token already set")]
+    MarketTokenAlreadySet,
+    #[msg("Pool vault balance does not cover the margin")]
+    InsufficientVaultBalance,
+
+
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base_config() -> MarketBaseConfig {
+        MarketBaseConfig {
+            min_margin_per_liquidity_position: 0,
+            max_leverage_per_liquidity_position: 0,
+            liquidation_fee_rate_per_liquidity_position: 0,
+            min_margin_per_position: 0,
+            max_leverage_per_position: 0,
+            liquidation_fee_rate_per_position: 50,
+            max_position_liquidity: 0,
+            max_position_value_rate: 0,
+            max_size_rate_per_position: 0,
+            liquidation_execution_fee: 10,
+            interest_rate: 0,
+            max_funding_rate: 0,
+            partial_liquidation_enabled: true,
+            partial_liquidation_buffer_rate: 1_000,
+            adl_deficit_threshold: 0,
+            risk_buffer_fund_gain_rate: 0,
+        }
+    }
 
+    // with these fees a position of 100 at a price of 100 has a maintenance margin of 100, so it needs
+    // an equity of (100 + 10) * 110% = 121 to hold its buffer
+    fn partial_liquidation_size(base_cfg: &MarketBaseConfig, size: u128, equity: i128) -> Result<u128> {
+        calculate_partial_liquidation_size(base_cfg, size, 100, 100, 50, equity)
+    }
+
+    #[test]
+    fn partial_liquidation_closes_the_buffer_shortfall() {
+        let base_cfg = base_config();
+        // a shortfall of 6 against 10% of the maintenance margin of 100 takes 60% of the size
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, 115).unwrap(), 60);
+        // rounded up to the next unit of size
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, 120).unwrap(), 10);
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, 121).unwrap(), 0);
+    }
+
+    #[test]
+    fn partial_liquidation_falls_back_to_the_whole_size() {
+        let mut base_cfg = base_config();
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, 100).unwrap(), 100);
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, 0).unwrap(), 100);
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, -5).unwrap(), 100);
+
+        base_cfg.partial_liquidation_buffer_rate = 0;
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, 115).unwrap(), 100);
+        base_cfg.partial_liquidation_buffer_rate = 1_000;
+        base_cfg.partial_liquidation_enabled = false;
+        assert_eq!(partial_liquidation_size(&base_cfg, 100, 115).unwrap(), 100);
+    }
 
+    #[test]
+    fn position_margin_respects_the_min_margin() {
+        let mut base_cfg = base_config();
+        base_cfg.min_margin_per_position = 10;
+        base_cfg.max_leverage_per_position = 10;
+        assert_eq!(validate_position_margin(&base_cfg, 9, 50, Q96).unwrap_err(), error!(ErrorCode::MarginBelowMinimum));
+        assert_eq!(validate_position_margin(&base_cfg, -1, 50, Q96).unwrap_err(), error!(ErrorCode::MarginBelowMinimum));
+        assert!(validate_position_margin(&base_cfg, 10, 50, Q96).is_ok());
+    }
+
+    #[test]
+    fn position_margin_respects_the_max_leverage() {
+        let mut base_cfg = base_config();
+        base_cfg.min_margin_per_position = 10;
+        base_cfg.max_leverage_per_position = 10;
+        // 100 at a price of 1 is exactly 10x on a margin of 10
+        assert!(validate_position_margin(&base_cfg, 10, 100, Q96).is_ok());
+        assert_eq!(validate_position_margin(&base_cfg, 10, 101, Q96).unwrap_err(), error!(ErrorCode::LeverageTooHigh));
+        // the notional is taken at the entry price
+        assert_eq!(validate_position_margin(&base_cfg, 10, 100, 2 * Q96).unwrap_err(), error!(ErrorCode::LeverageTooHigh));
+        assert!(validate_position_margin(&base_cfg, 20, 100, 2 * Q96).is_ok());
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn partial_liquidation_size_errors_on_overflow() {
+        let base_cfg = base_config();
+        let error = partial_liquidation_size(&base_cfg, 1 << 100, 1).unwrap_err();
+        assert_eq!(error, error!(ErrorCode::Overflow));
+    }
+}
@@ -1,14 +1,30 @@
 use anchor_lang::prelude::*;
+use governance::{Governance, PauseRegistry, GOVERNANCE_SEED, PAUSE_REGISTRY_SEED, PAUSE_CLOSING, PAUSE_ORDERS, TIMELOCK_AUTHORITY_SEED};
+use anchor_lang::system_program;
+use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use router::cpi::accounts::{DecreasePosition, IncreasePosition, PluginTransfer};
+use router::program::Router;
+use router::ContractState as RouterState;
+use liquidity_position_util::position_util::{Position, State as PoolState};
+use liquidity_position_util::program::Pool;
+use liquidity_position_util::POOL_AUTHORITY_SEED;
+use price_feed::{PriceFeed, TokenPrice};
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLn2");
-const GOVERNOR_PUBKEY: Pubkey = Pubkey::new_from_array([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32]);
+/// `sizeDelta` sentinel for decrease orders that close whatever size the position has at execution
+pub const CLOSE_ALL_SIZE_DELTA: u128 = u128::MAX;
+/// Share of an expired order's execution fee paid to whoever purges it, in basis points
+pub const PURGE_TIP_RATE: u128 = 1_000;
+const BASIS_POINTS_DIVISOR: u128 = 10_000;
+/// Seed of the PDA that owns the margin vault and acts as this plugin towards the router
+pub const ORDER_BOOK_AUTHORITY_SEED: &[u8] = b"order_book_authority";
 #[program]
 pub mod order_book {
     use super::*;
 
     // Initialize the order book
-    pub fn initialize(ctx: Context<Initialize>, min_execution_fee: u128 , execution_gas_limit : u128 , increase_order_index : u128 , decrease_order_index : u128 , usd : Pubkey , router : Pubkey ) -> Result<()> {
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);     
+    pub fn initialize(ctx: Context<Initialize>, min_execution_fee: u128 , execution_gas_limit : u128 , increase_order_index : u128 , decrease_order_index : u128 , usd : Pubkey , router : Pubkey , price_feed : Pubkey , index_token : Pubkey ) -> Result<()> {
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
         let state =&mut ctx.accounts.state;
         require!(!state.initilized , MyError::AlreadyInitlized );
         state.min_execution_fee = min_execution_fee;
@@ -17,12 +33,14 @@ pub mod order_book {
         state.decrease_order_index;
         state.usd = usd;
         state.router = router;
+        state.price_feed = price_feed;
+        state.index_token = index_token;
         Ok(())
     }
 
     pub fn add_executor(ctx: Context<UpdateExecutor>, new_executor: Pubkey) -> Result<()> {
         // Ensure the caller is the governor
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
 
         // Add new executor to the list
         let governance_state = &mut ctx.accounts.state;
@@ -32,7 +50,7 @@ pub mod order_book {
 
         // Function to update executor
     pub fn remove_executor(ctx: Context<UpdateExecutor>, executor: Pubkey) -> Result<()> {
-            require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+            governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
             let address_list = &mut ctx.accounts.state.executors;
             address_list.retain(|&x| x != executor);
             // Logic to update executor
@@ -41,14 +59,12 @@ pub mod order_book {
 
     // Update minimum execution fee
     pub fn update_min_execution_fee(ctx: Context<UpdateMinExecutionFee>, new_fee: u128) -> Result<()> {
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);     
         let state =&mut ctx.accounts.state;
         state.min_execution_fee = new_fee;
         Ok(())
     }
 
     pub fn update_execution_gas_limit(ctx: Context<UpdateMinExecutionFee>, new_limit: u128) -> Result<()> {
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);     
         let state: &mut Account<'_, ContractState> =&mut ctx.accounts.state;
         state.execution_gas_limit = new_limit;
         Ok(())
@@ -62,10 +78,31 @@ pub mod order_book {
         sizeDelta:u128,
         trigger_marketPriceX96 :u128,
         trigger_above : bool,
-        acceptable_trade_price:u128) -> Result<()> {
+        acceptable_trade_price:u128,
+        expiry : Option<i64>,
+        value : u128) -> Result<()> {
+        _validate_expiry(expiry)?;
+        _validate_acceptable_trade_price_x96(acceptable_trade_price)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, ctx.accounts.state.pool, PAUSE_ORDERS)?;
+        require!(value >= ctx.accounts.state.min_execution_fee, MyError::InsufficientExecutionFee);
+        _transfer_in_execution_fee(&ctx.accounts.authorized_account, &ctx.accounts.state.to_account_info(), &ctx.accounts.system_program, value)?;
         
         if margin_delta > 0  {
-            // router plugin transfer
+            // escrow the margin until the order is executed, cancelled or expires
+            let bump: u8 = ctx.bumps.order_book_authority;
+            let signer_seeds: &[&[&[u8]]] = &[&[ORDER_BOOK_AUTHORITY_SEED, &[bump]]];
+            let cpi_accounts: PluginTransfer<'_> = PluginTransfer{
+                authorized_account : ctx.accounts.authorized_account.to_account_info(),
+                state : ctx.accounts.router_state.to_account_info(),
+                user : ctx.accounts.order_book_authority.to_account_info(),
+                from_token : ctx.accounts.user_token_account.to_account_info(),
+                to_token : ctx.accounts.vault.to_account_info(),
+                router_authority : ctx.accounts.router_authority.to_account_info(),
+                token_program : ctx.accounts.token_program.to_account_info(),
+                plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            };
+            let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.router_program.to_account_info(), cpi_accounts, signer_seeds);
+            router::cpi::plugin_transfer(cpi_ctx , margin_delta , ctx.accounts.authorized_account.key() , ctx.accounts.order_book_authority.key())?;
         }
 
         let new_order = IncreaseOrder {
@@ -77,7 +114,8 @@ pub mod order_book {
             triggerMarketPriceX96: trigger_marketPriceX96, 
             triggerAbove: trigger_above,
             acceptableTradePriceX96: acceptable_trade_price,
-            executionFee: 0, // Replace with your actual execution fee
+            executionFee: value,
+            expiry : expiry,
         };
 
         ctx.accounts.state.all_increase_orders.push(new_order);
@@ -86,7 +124,8 @@ pub mod order_book {
             sizeDelta,
             trigger_marketPriceX96 ,
             trigger_above ,
-            acceptable_trade_price});
+            acceptable_trade_price,
+            expiry});
         Ok(())
     }
 
@@ -96,11 +135,15 @@ pub mod order_book {
         trigger_marketPriceX96 :u128,
         trigger_above : bool,
         acceptable_trade_price:u128 , 
-    receiver : Pubkey) -> Result<()> {
-        
-        if margin_delta > 0  {
-            // router plugin transfer
-        }
+    receiver : Pubkey,
+        reduce_only : bool,
+        expiry : Option<i64>,
+        value : u128) -> Result<()> {
+        _validate_expiry(expiry)?;
+        _validate_acceptable_trade_price_x96(acceptable_trade_price)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, ctx.accounts.state.pool, PAUSE_ORDERS)?;
+        require!(value >= ctx.accounts.state.min_execution_fee, MyError::InsufficientExecutionFee);
+        _transfer_in_execution_fee(&ctx.accounts.authorized_account, &ctx.accounts.state.to_account_info(), &ctx.accounts.system_program, value)?;
 
         let new_order = DecreaseOrder {
             account: ctx.accounts.authorized_account.key(), 
@@ -111,8 +154,10 @@ pub mod order_book {
             triggerMarketPriceX96: trigger_marketPriceX96, 
             triggerAbove: trigger_above, 
             acceptableTradePriceX96: acceptable_trade_price,
-            executionFee: 0, // Replace with your actual execution fee
-            receiver : receiver
+            executionFee: value,
+            receiver : receiver,
+            reduce_only : reduce_only,
+            expiry : expiry
         };
 
         ctx.accounts.state.all_decrease_orders.push(new_order);
@@ -121,15 +166,17 @@ pub mod order_book {
             sizeDelta,
             trigger_marketPriceX96 ,
             trigger_above ,
-            acceptable_trade_price});
+            acceptable_trade_price,
+            expiry});
         Ok(())
     }
 
     pub fn update_decrease_order(ctx: Context<CreateDecreaseOrder>, order_index: u128, trigger_market_price: u128, acceptable_trade_price: u128) -> Result<()> {
         let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
         let index_usize: usize = order_index as usize;
-        let order: &mut DecreaseOrder = &mut state.all_decrease_orders[index_usize];
+        let order: &mut DecreaseOrder = state.all_decrease_orders.get_mut(index_usize).ok_or(MyError::InvalidOperation)?;
         require!(order.account == ctx.accounts.authorized_account.key(), MyError::CallerUnauthorized);
+        _validate_acceptable_trade_price_x96(acceptable_trade_price)?;
         order.triggerMarketPriceX96 = trigger_market_price;
         order.acceptableTradePriceX96 = acceptable_trade_price;
         emit!(DecreaseOrderUpdated{
@@ -142,11 +189,12 @@ pub mod order_book {
     }
     
 
-    pub fn update_increase_order(ctx: Context<CreateIncreaseOrder> , order_index :u128 , trigger_market_price : u128 , acceptable_trade_price: u128) -> Result<()> {
+    pub fn update_increase_order(ctx: Context<UpdateIncreaseOrder> , order_index :u128 , trigger_market_price : u128 , acceptable_trade_price: u128) -> Result<()> {
         let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
         let index_usize: usize = order_index as usize;
-        let order: &mut IncreaseOrder = &mut state.all_increase_orders[index_usize];
+        let order: &mut IncreaseOrder = state.all_increase_orders.get_mut(index_usize).ok_or(MyError::InvalidOperation)?;
         require!(order.account == ctx.accounts.authorized_account.key(), MyError::CallerUnauthorized);
+        _validate_acceptable_trade_price_x96(acceptable_trade_price)?;
         order.triggerMarketPriceX96 = trigger_market_price;
         order.acceptableTradePriceX96 = acceptable_trade_price;
         emit!(IncreaseOrderUpdated{
@@ -158,15 +206,24 @@ pub mod order_book {
         Ok(())
     }
 
-    pub fn cancel_increase_order(ctx: Context<CreateIncreaseOrder> , order_index :u128 , fee_reciever: Pubkey ) -> Result<()> {
+    pub fn cancel_increase_order(ctx: Context<CancelIncreaseOrder> , order_index :u128 , fee_reciever: Pubkey ) -> Result<()> {
+        let state_info: AccountInfo<'_> = ctx.accounts.state.to_account_info();
         let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
         let index_usize: usize = order_index as usize;
-        let order: &mut IncreaseOrder = &mut state.all_increase_orders[index_usize];
+        let order: &mut IncreaseOrder = state.all_increase_orders.get_mut(index_usize).ok_or(MyError::InvalidOperation)?;
         require!(order.account == ctx.accounts.authorized_account.key(), MyError::CallerUnauthorized);
         require!(order.account!=Pubkey::default() , MyError::InvalidOperation);
-        // token tranfer 
-        // sol transfer
-        state.all_increase_orders.remove(index_usize);
+        require!(ctx.accounts.user_token_account.owner == order.account , MyError::OwnerMismatch);
+        let order: IncreaseOrder = state.all_increase_orders.remove(index_usize);
+        _transfer_out_margin(
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.order_book_authority,
+            &ctx.accounts.token_program,
+            ctx.bumps.order_book_authority,
+            order.marginDelta,
+        )?;
+        _transfer_out_execution_fee(&state_info, &ctx.accounts.authorized_account, order.executionFee)?;
         emit!(IncreaseOrderCancelled{
             _orderIndex : order_index ,
             fee_receiver : fee_reciever
@@ -174,32 +231,70 @@ pub mod order_book {
         Ok(())
     }
 
-    pub fn execute_increase_order(ctx: Context<CreateIncreaseOrder> , order_index :u128 , fee_reciever: Pubkey ) -> Result<()> {
-        let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
+    // Executes the increase order at `order_index` once the market price crosses its trigger, paying its execution fee to the executor.
+    // The remaining accounts are the oracle accounts of the index token, passed on to the pool.
+    pub fn execute_increase_order<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteIncreaseOrder<'info>> , order_index :u128 ) -> Result<()> {
+        let executor: Pubkey = ctx.accounts.authorized_account.key();
+        require!(ctx.accounts.state.executors.contains(&executor), MyError::CallerUnauthorized);
         let index_usize: usize = order_index as usize;
-        let order: &mut IncreaseOrder = &mut state.all_increase_orders[index_usize];
-        // external call to pool required here 
-        let market_price = 100;
-        _validate_trade_price_X96(order.side , market_price , order.triggerMarketPriceX96);
-
-        // external call to router 
-        // sol transfer out 
-        state.all_increase_orders.remove(index_usize);
+        let order: IncreaseOrder = ctx.accounts.state.all_increase_orders.get(index_usize).ok_or(MyError::InvalidOperation)?.clone();
+        require!(order.account == ctx.accounts.owner.key(), MyError::OwnerMismatch);
+        require!(order.pool == ctx.accounts.pool_state.key(), MyError::InvalidOperation);
+        governance::require_not_paused(&ctx.accounts.pause_registry, order.pool, PAUSE_ORDERS)?;
+        require!(!_is_expired(order.expiry, Clock::get()?.unix_timestamp), MyError::OrderExpired);
+        // increases are triggered against the max price for longs and the min price for shorts
+        let market_price: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.index_token, order.side, ctx.remaining_accounts)?;
+        _validate_trigger_price_x96(order.triggerAbove , market_price , order.triggerMarketPriceX96)?;
+
+        // the escrowed margin goes to the pool before the router opens the position with it
+        let bump: u8 = ctx.bumps.order_book_authority;
+        _transfer_out_margin(
+            &ctx.accounts.vault.to_account_info(),
+            &ctx.accounts.pool_vault.to_account_info(),
+            &ctx.accounts.order_book_authority,
+            &ctx.accounts.token_program,
+            bump,
+            order.marginDelta,
+        )?;
+        let signer_seeds: &[&[&[u8]]] = &[&[ORDER_BOOK_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: IncreasePosition<'info> = IncreasePosition{
+            authorized_account : ctx.accounts.owner.to_account_info(),
+            state : ctx.accounts.router_state.to_account_info(),
+            user : ctx.accounts.order_book_authority.to_account_info(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.router_program.to_account_info(), cpi_accounts, signer_seeds)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        let trade_price: u128 = router::cpi::plugin_increase_position(cpi_ctx , order.pool , order.account , order.side , order.marginDelta , order.sizeDelta)?.get();
+        _validate_trade_price_X96(order.side , trade_price , order.acceptableTradePriceX96)?;
+
+        ctx.accounts.state.all_increase_orders.remove(index_usize);
+        _transfer_out_execution_fee(&ctx.accounts.state.to_account_info(), &ctx.accounts.authorized_account, order.executionFee)?;
         emit!(IncreaseOrderExecuted{
             _orderIndex : order_index ,
             market_price : market_price ,
-            fee_receiver : fee_reciever
+            fee_receiver : executor
         }  );
         Ok(())
     }
 
-    pub fn cancel_decrease_order(ctx: Context<CreateIncreaseOrder> , order_index :u128 , fee_reciever: Pubkey ) -> Result<()> {
+    pub fn cancel_decrease_order(ctx: Context<CancelDecreaseOrder> , order_index :u128 , fee_reciever: Pubkey ) -> Result<()> {
+        let state_info: AccountInfo<'_> = ctx.accounts.state.to_account_info();
         let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
         let index_usize: usize = order_index as usize;
-        let order: &mut DecreaseOrder = &mut state.all_decrease_orders[index_usize];
+        let order: &mut DecreaseOrder = state.all_decrease_orders.get_mut(index_usize).ok_or(MyError::InvalidOperation)?;
         require!(order.account == ctx.accounts.authorized_account.key(), MyError::CallerUnauthorized);
-        //  sol tranfer out 
+        let execution_fee: u128 = order.executionFee;
         state.all_decrease_orders.remove(index_usize);
+        _transfer_out_execution_fee(&state_info, &ctx.accounts.authorized_account, execution_fee)?;
 
         emit!(DecreaseOrderCancelled{
             _orderIndex : order_index ,
@@ -208,35 +303,73 @@ pub mod order_book {
         Ok(())
     }
 
-    pub fn execute_decrease_order(ctx: Context<CreateIncreaseOrder> , order_index :u128 , fee_reciever: Pubkey ) -> Result<()> {
-        let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
+    // Executes the decrease order at `order_index` once the market price crosses its trigger, paying its execution fee to the executor.
+    // The order is cancelled and its fee refunded instead when the position is gone. The remaining accounts are the
+    // oracle accounts of the index token, passed on to the pool.
+    pub fn execute_decrease_order<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteDecreaseOrder<'info>> , order_index :u128 ) -> Result<()> {
+        let executor: Pubkey = ctx.accounts.authorized_account.key();
+        require!(ctx.accounts.state.executors.contains(&executor), MyError::CallerUnauthorized);
+        let state_info: AccountInfo<'info> = ctx.accounts.state.to_account_info();
         let index_usize: usize = order_index as usize;
-        let order: &mut DecreaseOrder = &mut state.all_decrease_orders[index_usize];
-        // external call to pool required here 
-        let market_price: u128 = 100;
-        _validate_trade_price_X96(order.side , market_price , order.triggerMarketPriceX96);
-        let size_delta_after: u128 = order.sizeDelta;
-        let margin_delta_after: u128 = order.marginDelta;
-        if order.sizeDelta == 0{
-            // external call to pool 
-        }
+        let order: DecreaseOrder = ctx.accounts.state.all_decrease_orders.get(index_usize).ok_or(MyError::InvalidOperation)?.clone();
+        require!(order.account == ctx.accounts.owner.key(), MyError::OwnerMismatch);
+        require!(order.pool == ctx.accounts.pool_state.key(), MyError::InvalidOperation);
+        governance::require_not_paused(&ctx.accounts.pause_registry, order.pool, PAUSE_ORDERS | PAUSE_CLOSING)?;
+        require!(!_is_expired(order.expiry, Clock::get()?.unix_timestamp), MyError::OrderExpired);
+
+        // the position may have been closed or liquidated since the order was placed
+        let Some(position) = _open_position(&ctx.accounts.pool_state.positions, order.account, order.side) else {
+            ctx.accounts.state.all_decrease_orders.remove(index_usize);
+            _transfer_out_execution_fee(&state_info, &ctx.accounts.owner, order.executionFee)?;
+            emit!(DecreaseOrderCancelled{
+                _orderIndex : order_index ,
+                fee_receiver : order.account
+            });
+            return Ok(())
+        };
+        let (size_delta_after, margin_delta_after) = _resolve_decrease_order_deltas(&order, position.size, position.margin);
+
+        // decreases are triggered against the min price for longs and the max price for shorts
+        let market_price: u128 = price_feed::get_index_price(&ctx.accounts.price_feed, &ctx.accounts.token_price, ctx.accounts.state.index_token, !order.side, ctx.remaining_accounts)?;
+        _validate_trigger_price_x96(order.triggerAbove , market_price , order.triggerMarketPriceX96)?;
 
-        // external call to router 
-        let trade_price = 0;
-        if order.sizeDelta != 0 {
-            _validate_trade_price_X96(order.side , trade_price , order.acceptableTradePriceX96);
+        let bump: u8 = ctx.bumps.order_book_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ORDER_BOOK_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: DecreasePosition<'info> = DecreasePosition{
+            authorized_account : ctx.accounts.owner.to_account_info(),
+            state : ctx.accounts.router_state.to_account_info(),
+            user : ctx.accounts.order_book_authority.to_account_info(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.router_program.to_account_info(), cpi_accounts, signer_seeds)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        let trade_price: u128 = router::cpi::plugin_decrease_position(cpi_ctx , order.pool , order.account , order.side , margin_delta_after , size_delta_after , order.receiver)?.get();
+        if size_delta_after != 0 {
+            // closing trades against the position's side
+            _validate_trade_price_X96(!order.side , trade_price , order.acceptableTradePriceX96)?;
         }
-        // sol transfer out 
-        state.all_decrease_orders.remove(index_usize);
+
+        ctx.accounts.state.all_decrease_orders.remove(index_usize);
+        _transfer_out_execution_fee(&state_info, &ctx.accounts.authorized_account, order.executionFee)?;
         emit!(DecreaseOrderExecuted{
             _orderIndex : order_index ,
             market_price : market_price ,
-            fee_receiver : fee_reciever
+            fee_receiver : executor
         }  );
         Ok(())
     }
 
-    pub fn create_take_profit_and_stop_loss_orders(ctx: Context<CreateIncreaseOrder>,
+    pub fn create_take_profit_and_stop_loss_orders(ctx: Context<CreateDecreaseOrder>,
         pool: Pubkey,
         side: bool,
         margin_deltas: [u128; 2],
@@ -245,12 +378,18 @@ pub mod order_book {
         acceptable_trade_price: [u128; 2],
         receiver: Pubkey,
         value : u128 , 
+        expiry : Option<i64>,
     ) -> Result<()>  {
+        _validate_expiry(expiry)?;
+        _validate_acceptable_trade_price_x96(acceptable_trade_price[0])?;
+        _validate_acceptable_trade_price_x96(acceptable_trade_price[1])?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, ctx.accounts.state.pool, PAUSE_ORDERS)?;
         let fee0: u128  = value/2;
         let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
         if(fee0 < state.min_execution_fee){
             return err!(MyError::InsufficientExecutionFee);
         }
+        _transfer_in_execution_fee(&ctx.accounts.authorized_account, &ctx.accounts.state.to_account_info(), &ctx.accounts.system_program, value)?;
 
         let new_order = DecreaseOrder {
             account: ctx.accounts.authorized_account.key(), 
@@ -262,7 +401,9 @@ pub mod order_book {
             triggerAbove: side, 
             acceptableTradePriceX96: acceptable_trade_price[0],
             executionFee: fee0, // Replace with your actual execution fee
-            receiver : receiver
+            receiver : receiver,
+            reduce_only : true,
+            expiry : expiry
         };
 
         ctx.accounts.state.all_decrease_orders.push(new_order);
@@ -273,29 +414,33 @@ pub mod order_book {
             trigger_marketPriceX96: trigger_market_price[0], 
             trigger_above: side, 
             acceptable_trade_price: acceptable_trade_price[0],
+            expiry,
             });
 
             let new_order = DecreaseOrder {
                 account: ctx.accounts.authorized_account.key(), 
                 pool: ctx.accounts.state.pool, 
-                side: !side, 
+                side, 
                 marginDelta: margin_deltas[1], 
                 sizeDelta: size_deltas[1], 
                 triggerMarketPriceX96: trigger_market_price[1], 
                 triggerAbove: !side, 
                 acceptableTradePriceX96: acceptable_trade_price[1],
                 executionFee: value - fee0, // Replace with your actual execution fee
-                receiver : receiver
+                receiver : receiver,
+                reduce_only : true,
+                expiry : expiry
             };
     
             ctx.accounts.state.all_decrease_orders.push(new_order);
             emit!(CreateDecreaseOrderEvent{
-                side: !side, 
+                side, 
                 margin_delta: margin_deltas[1], 
                 sizeDelta: size_deltas[1], 
                 trigger_marketPriceX96: trigger_market_price[1], 
                 trigger_above: !side, 
                 acceptable_trade_price: acceptable_trade_price[1],
+                expiry,
                 });
     Ok(())
     }
@@ -304,9 +449,157 @@ pub mod order_book {
 
 
 
+    // Cancels the expired orders at `increase_order_indexes` and `decrease_order_indexes`, refunding owners their margin
+    // and execution fee minus a tip for the caller. Each list must be strictly increasing and the indexes are the ones
+    // before any order of the call is removed, so a caller can purge in bounded batches.
+    // Owners of the orders are passed as remaining accounts, increase orders first, in the order of the indexes.
+    // Each increase order takes the owner followed by the owner's token account for the margin refund.
+    pub fn purge_expired_orders<'info>(ctx: Context<'_, '_, 'info, 'info, PurgeExpiredOrders<'info>>, increase_order_indexes: Vec<u32>, decrease_order_indexes: Vec<u32>) -> Result<()> {
+        require!(_is_strictly_increasing(&increase_order_indexes) && _is_strictly_increasing(&decrease_order_indexes), MyError::InvalidOperation);
+        let now: i64 = Clock::get()?.unix_timestamp;
+        let state_info: AccountInfo<'info> = ctx.accounts.state.to_account_info();
+        let executor_info: AccountInfo<'info> = ctx.accounts.executor.to_account_info();
+        let state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
+        let (increase_accounts, decrease_accounts) = ctx.remaining_accounts.split_at(
+            (increase_order_indexes.len() * 2).min(ctx.remaining_accounts.len())
+        );
+        require!(increase_accounts.len() == increase_order_indexes.len() * 2 && decrease_accounts.len() == decrease_order_indexes.len(), MyError::OwnerMismatch);
+        let mut tip: u128 = 0;
+
+        // removing from the back keeps the lower indexes of the list in place
+        for (&index, accounts) in increase_order_indexes.iter().zip(increase_accounts.chunks(2)).rev() {
+            let order: &IncreaseOrder = state.all_increase_orders.get(index as usize).ok_or(MyError::InvalidOperation)?;
+            require!(_is_expired(order.expiry, now), MyError::OrderNotExpired);
+            let (owner, owner_token_account) = (&accounts[0], &accounts[1]);
+            require!(owner.key() == order.account, MyError::OwnerMismatch);
+            require!(Account::<TokenAccount>::try_from(owner_token_account)?.owner == owner.key(), MyError::OwnerMismatch);
+            let order: IncreaseOrder = state.all_increase_orders.remove(index as usize);
+            _transfer_out_margin(
+                &ctx.accounts.vault.to_account_info(),
+                owner_token_account,
+                &ctx.accounts.order_book_authority,
+                &ctx.accounts.token_program,
+                ctx.bumps.order_book_authority,
+                order.marginDelta,
+            )?;
+            let order_tip: u128 = _purge_tip(order.executionFee)?;
+            _transfer_out_execution_fee(&state_info, owner, order.executionFee - order_tip)?;
+            tip = tip.checked_add(order_tip).ok_or(MyError::Overflow)?;
+            emit!(IncreaseOrderCancelled{
+                _orderIndex : index as u128 ,
+                fee_receiver : order.account
+            });
+        }
+
+        for (&index, owner) in decrease_order_indexes.iter().zip(decrease_accounts.iter()).rev() {
+            let order: &DecreaseOrder = state.all_decrease_orders.get(index as usize).ok_or(MyError::InvalidOperation)?;
+            require!(_is_expired(order.expiry, now), MyError::OrderNotExpired);
+            require!(owner.key() == order.account, MyError::OwnerMismatch);
+            let order: DecreaseOrder = state.all_decrease_orders.remove(index as usize);
+            let order_tip: u128 = _purge_tip(order.executionFee)?;
+            _transfer_out_execution_fee(&state_info, owner, order.executionFee - order_tip)?;
+            tip = tip.checked_add(order_tip).ok_or(MyError::Overflow)?;
+            emit!(DecreaseOrderCancelled{
+                _orderIndex : index as u128 ,
+                fee_receiver : order.account
+            });
+        }
+
+        _transfer_out_execution_fee(&state_info, &executor_info, tip)?;
+        Ok(())
+    }
+
     // ... Add other functions here
 }
 
+// Share of an expired order's execution fee tipped to the purger
+pub fn _purge_tip(execution_fee : u128) -> Result<u128> {
+    Ok(execution_fee.checked_mul(PURGE_TIP_RATE).ok_or(MyError::Overflow)? / BASIS_POINTS_DIVISOR)
+}
+
+pub fn _is_strictly_increasing(indexes : &[u32]) -> bool {
+    indexes.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+pub fn _is_expired(expiry : Option<i64> , now : i64) -> bool {
+    matches!(expiry, Some(expiry) if now >= expiry)
+}
+
+pub fn _validate_expiry(expiry : Option<i64>) -> Result<()> {
+    if let Some(expiry) = expiry {
+        require!(expiry > Clock::get()?.unix_timestamp, MyError::OrderExpired);
+    }
+    Ok(())
+}
+
+/// The `account`'s position on `side`, unless it has been closed.
+pub fn _open_position(positions : &[Position] , account : Pubkey , side : bool) -> Option<&Position> {
+    positions.iter().find(|position| position.account == account && position.side == side && position.size > 0)
+}
+
+/// Resolves the `(sizeDelta, marginDelta)` a decrease order executes with against the live position.
+/// Close-all and oversized reduce-only orders are clamped to the position size.
+pub fn _resolve_decrease_order_deltas(order : &DecreaseOrder , position_size : u128 , position_margin : u128) -> (u128 , u128) {
+    if order.sizeDelta == CLOSE_ALL_SIZE_DELTA || (order.reduce_only && order.sizeDelta >= position_size) {
+        // closing the whole position releases all of its margin anyway
+        return (position_size , 0);
+    }
+    if order.sizeDelta == 0 {
+        return (0 , std::cmp::min(order.marginDelta , position_margin));
+    }
+    (order.sizeDelta , order.marginDelta)
+}
+
+pub fn _transfer_out_margin<'info>(vault : &AccountInfo<'info> , to : &AccountInfo<'info> , authority : &AccountInfo<'info> , token_program : &Program<'info, Token> , bump : u8 , amount : u128) -> Result<()> {
+    if amount == 0 {
+        return Ok(())
+    }
+    let amount: u64 = u64::try_from(amount).map_err(|_| MyError::InvalidOperation)?;
+    let signer_seeds: &[&[&[u8]]] = &[&[ORDER_BOOK_AUTHORITY_SEED, &[bump]]];
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), Transfer{
+        from : vault.clone(),
+        to : to.clone(),
+        authority : authority.clone(),
+    }, signer_seeds);
+    token::transfer(cpi_ctx, amount)
+}
+
+pub fn _transfer_in_execution_fee<'info>(payer : &AccountInfo<'info> , state : &AccountInfo<'info> , system_program : &Program<'info, System> , amount : u128) -> Result<()> {
+    if amount == 0 {
+        return Ok(())
+    }
+    let amount: u64 = u64::try_from(amount).map_err(|_| MyError::InvalidOperation)?;
+    let cpi_ctx = CpiContext::new(system_program.to_account_info(), system_program::Transfer{
+        from : payer.clone(),
+        to : state.clone(),
+    });
+    system_program::transfer(cpi_ctx, amount)
+}
+
+// the state account is owned by this program, so fees held in it can be debited directly
+pub fn _transfer_out_execution_fee(state : &AccountInfo , receiver : &AccountInfo , amount : u128) -> Result<()> {
+    if amount == 0 {
+        return Ok(())
+    }
+    let amount: u64 = u64::try_from(amount).map_err(|_| MyError::InvalidOperation)?;
+    **state.try_borrow_mut_lamports()? -= amount;
+    **receiver.try_borrow_mut_lamports()? += amount;
+    Ok(())
+}
+
+// Errors unless the market price is at or beyond the trigger price in the order's direction
+pub fn _validate_trigger_price_x96(trigger_above : bool , market_price_x96 : u128 , trigger_price_x96 : u128) -> Result<()> {
+    let triggered: bool = if trigger_above { market_price_x96 >= trigger_price_x96 } else { market_price_x96 <= trigger_price_x96 };
+    require!(triggered , MyError::InvalidMarketPriceToTrigger);
+    Ok(())
+}
+
+// Orders always carry slippage protection, a zero acceptable trade price would let any trade price through on a decrease
+pub fn _validate_acceptable_trade_price_x96(acceptable_trade_price_x96 : u128) -> Result<()> {
+    require!(acceptable_trade_price_x96 > 0 , MyError::InvalidAcceptableTradePrice);
+    Ok(())
+}
+
 pub fn  _validate_trade_price_X96(_side : bool, _tradePriceX96 : u128,  _acceptableTradePriceX96 : u128) -> Result<()> {
     if _side && (_tradePriceX96 > _acceptableTradePriceX96) || (!_side && (_tradePriceX96 < _acceptableTradePriceX96) )  {
         require!(false , MyError::InvalidOperation);
@@ -324,6 +617,8 @@ pub struct ContractState {
     pool : Pubkey,
     usd : Pubkey,
     price_feed : Pubkey,
+    // token the pool's index price is read for
+    index_token : Pubkey,
     initilized: bool,
     min_execution_fee: u128 ,
     execution_gas_limit : u128 , 
@@ -342,6 +637,7 @@ pub struct IncreaseOrder{
     triggerAbove :bool,
     acceptableTradePriceX96 : u128,
     executionFee:u128,
+    expiry : Option<i64>,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -356,6 +652,8 @@ pub struct DecreaseOrder{
     acceptableTradePriceX96 : u128,
     executionFee:u128,
     receiver:Pubkey,
+    reduce_only : bool,
+    expiry : Option<i64>,
 }
 
 
@@ -367,16 +665,17 @@ pub struct Initialize<'info> {
     #[account(signer)]
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
-
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 // Update minimum execution fee context
 #[derive(Accounts)]
 pub struct UpdateMinExecutionFee<'info> {
-     // Adjust space as needed
-    /// CHECK
-    #[account(signer)]
-    pub authorized_account: AccountInfo<'info>,
+    // only queued changes reach this, signed by the governance timelock once their eta passed
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump, seeds::program = governance::ID)]
+    pub timelock_authority: Signer<'info>,
+    #[account(mut)]
     pub state: Account<'info, ContractState>,
 }
 
@@ -397,6 +696,8 @@ pub struct UpdateExecutor<'info> {
     #[account(signer)]
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 // Create increase order context
@@ -404,9 +705,103 @@ pub struct UpdateExecutor<'info> {
 pub struct CreateIncreaseOrder<'info> {
      // Adjust space as needed
     /// CHECK
+    #[account(mut, signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub state: Account<'info, ContractState>,
+    pub system_program: Program<'info, System>,
+    /// CHECK: PDA signing for this plugin and owning the margin vault
+    #[account(seeds = [ORDER_BOOK_AUTHORITY_SEED], bump)]
+    pub order_book_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(mut, constraint = vault.owner == order_book_authority.key())]
+    pub vault: Account<'info, TokenAccount>,
+    pub router_state: Account<'info, RouterState>,
+    /// CHECK: delegate PDA of the router, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    pub router_program: Program<'info, Router>,
+    /// CHECK: the order owner's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateIncreaseOrder<'info> {
+    /// CHECK
     #[account(signer)]
     pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub state: Account<'info, ContractState>,
+}
+
+#[derive(Accounts)]
+pub struct CancelIncreaseOrder<'info> {
+    /// CHECK
+    #[account(mut, signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
     pub state: Account<'info, ContractState>,
+    /// CHECK: PDA owning the margin vault
+    #[account(seeds = [ORDER_BOOK_AUTHORITY_SEED], bump)]
+    pub order_book_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub user_token_account: Account<'info, TokenAccount>,
+    #[account(mut, constraint = vault.owner == order_book_authority.key())]
+    pub vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteIncreaseOrder<'info> {
+    /// CHECK: executor, checked against the whitelist and paid the execution fee
+    #[account(mut, signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub state: Account<'info, ContractState>,
+    /// CHECK: order owner the position is opened for
+    pub owner: AccountInfo<'info>,
+    /// CHECK: PDA signing for this plugin and owning the margin vault
+    #[account(seeds = [ORDER_BOOK_AUTHORITY_SEED], bump)]
+    pub order_book_authority: AccountInfo<'info>,
+    #[account(mut, constraint = vault.owner == order_book_authority.key())]
+    pub vault: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub pool_state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump, seeds::program = liquidity_position_util::ID)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key() && pool_vault.mint == vault.mint)]
+    pub pool_vault: Account<'info, TokenAccount>,
+    pub pool_program: Program<'info, Pool>,
+    pub router_state: Account<'info, RouterState>,
+    pub router_program: Program<'info, Router>,
+    /// CHECK: PDA the router signs pool calls with, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: the order owner's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    #[account(constraint = price_feed.key() == state.price_feed @ MyError::InvalidOperation)]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub token_price: Account<'info, TokenPrice>,
+}
+
+#[derive(Accounts)]
+pub struct PurgeExpiredOrders<'info> {
+    #[account(mut)]
+    pub executor: Signer<'info>,
+    #[account(mut)]
+    pub state: Account<'info, ContractState>,
+    /// CHECK: PDA owning the margin vault
+    #[account(seeds = [ORDER_BOOK_AUTHORITY_SEED], bump)]
+    pub order_book_authority: AccountInfo<'info>,
+    #[account(mut, constraint = vault.owner == order_book_authority.key())]
+    pub vault: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
 }
 
 // Create increase order context
@@ -414,9 +809,60 @@ pub struct CreateIncreaseOrder<'info> {
 pub struct CreateDecreaseOrder<'info> {
      // Adjust space as needed
     /// CHECK
-    #[account(signer)]
+    #[account(mut, signer)]
     pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
     pub state: Account<'info, ContractState>,
+    pub system_program: Program<'info, System>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct CancelDecreaseOrder<'info> {
+    /// CHECK: order owner, refunded the execution fee
+    #[account(mut, signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub state: Account<'info, ContractState>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDecreaseOrder<'info> {
+    /// CHECK: executor, checked against the whitelist and paid the execution fee
+    #[account(mut, signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub state: Account<'info, ContractState>,
+    /// CHECK: order owner, refunded the execution fee if the order is auto-cancelled
+    #[account(mut)]
+    pub owner: AccountInfo<'info>,
+    /// CHECK: PDA signing for this plugin
+    #[account(seeds = [ORDER_BOOK_AUTHORITY_SEED], bump)]
+    pub order_book_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub pool_state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault
+    #[account(seeds = [POOL_AUTHORITY_SEED], bump, seeds::program = liquidity_position_util::ID)]
+    pub pool_authority: AccountInfo<'info>,
+    #[account(mut, constraint = pool_vault.owner == pool_authority.key())]
+    pub pool_vault: Account<'info, TokenAccount>,
+    // token account of the order's receiver, validated by the pool
+    #[account(mut)]
+    pub receiver_token: Account<'info, TokenAccount>,
+    pub pool_program: Program<'info, Pool>,
+    pub router_state: Account<'info, RouterState>,
+    pub router_program: Program<'info, Router>,
+    /// CHECK: PDA the router signs pool calls with, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: the order owner's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    #[account(constraint = price_feed.key() == state.price_feed @ MyError::InvalidOperation)]
+    pub price_feed: Account<'info, PriceFeed>,
+    pub token_price: Account<'info, TokenPrice>,
 }
 
 // OrderBook state
@@ -441,7 +887,19 @@ pub enum MyError {
     #[msg("Program Already initilized")]
     AlreadyInitlized,
     #[msg("Insufficient Fee")]
-    InsufficientExecutionFee
+    InsufficientExecutionFee,
+    #[msg("Owner Mismatch")]
+    OwnerMismatch,
+    #[msg("Order expired")]
+    OrderExpired,
+    #[msg("Order not expired")]
+    OrderNotExpired,
+    #[msg("Market price has not reached the trigger price")]
+    InvalidMarketPriceToTrigger,
+    #[msg("Acceptable trade price must be set")]
+    InvalidAcceptableTradePrice,
+    #[msg("Amount overflow")]
+    Overflow,
 
 }
 
@@ -452,7 +910,8 @@ pub struct CreateIncreaseOrderEvent {
     sizeDelta:u128,
     trigger_marketPriceX96 :u128,
     trigger_above : bool,
-    acceptable_trade_price:u128
+    acceptable_trade_price:u128,
+    expiry : Option<i64>,
 }
 
 #[event]
@@ -462,7 +921,8 @@ pub struct CreateDecreaseOrderEvent {
     sizeDelta:u128,
     trigger_marketPriceX96 :u128,
     trigger_above : bool,
-    acceptable_trade_price:u128
+    acceptable_trade_price:u128,
+    expiry : Option<i64>,
 }
 #[event]
 pub struct DecreaseOrderUpdated{
@@ -508,4 +968,74 @@ pub struct DecreaseOrderExecuted{
      _orderIndex : u128,
      market_price : u128 , 
     fee_receiver : Pubkey ,
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decrease_order(margin_delta: u128, size_delta: u128, reduce_only: bool) -> DecreaseOrder {
+        DecreaseOrder{
+            account : Pubkey::new_unique(),
+            pool : Pubkey::new_unique(),
+            side : true,
+            marginDelta : margin_delta,
+            sizeDelta : size_delta,
+            triggerMarketPriceX96 : 0,
+            triggerAbove : true,
+            acceptableTradePriceX96 : 0,
+            executionFee : 0,
+            receiver : Pubkey::new_unique(),
+            reduce_only,
+            expiry : None,
+        }
+    }
+
+    fn position(account: Pubkey, side: bool, size: u128) -> Position {
+        Position{ account , side , margin : 100 , size , entry_price_x96 : 1 , entry_funding_rate_growth_x96 : 0 }
+    }
+
+    #[test]
+    fn close_all_orders_close_the_whole_position() {
+        assert_eq!(_resolve_decrease_order_deltas(&decrease_order(30, CLOSE_ALL_SIZE_DELTA, false), 50, 100), (50, 0));
+    }
+
+    #[test]
+    fn reduce_only_orders_are_capped_at_the_position_size() {
+        assert_eq!(_resolve_decrease_order_deltas(&decrease_order(30, 80, true), 50, 100), (50, 0));
+        assert_eq!(_resolve_decrease_order_deltas(&decrease_order(30, 20, true), 50, 100), (20, 30));
+        assert_eq!(_resolve_decrease_order_deltas(&decrease_order(30, 80, false), 50, 100), (80, 30));
+    }
+
+    #[test]
+    fn margin_only_orders_are_capped_at_the_position_margin() {
+        assert_eq!(_resolve_decrease_order_deltas(&decrease_order(30, 0, false), 50, 100), (0, 30));
+        assert_eq!(_resolve_decrease_order_deltas(&decrease_order(300, 0, false), 50, 100), (0, 100));
+    }
+
+    #[test]
+    fn orders_on_a_closed_position_are_cancelled() {
+        let account = Pubkey::new_unique();
+        let positions = vec![position(account, false, 10), position(account, true, 0)];
+        // no open long: the order is auto-cancelled
+        assert!(_open_position(&positions, account, true).is_none());
+        assert!(_open_position(&positions, Pubkey::new_unique(), false).is_none());
+        assert_eq!(_open_position(&positions, account, false).unwrap().size, 10);
+    }
+
+    #[test]
+    fn triggers_follow_the_order_direction() {
+        assert!(_validate_trigger_price_x96(true, 100, 100).is_ok());
+        assert!(_validate_trigger_price_x96(true, 99, 100).is_err());
+        assert!(_validate_trigger_price_x96(false, 100, 100).is_ok());
+        assert!(_validate_trigger_price_x96(false, 101, 100).is_err());
+    }
+
+    #[test]
+    fn orders_need_an_acceptable_trade_price() {
+        assert!(_validate_acceptable_trade_price_x96(0).is_err());
+        assert!(_validate_acceptable_trade_price_x96(1).is_ok());
+        // buys are rejected above the acceptable price and sells below it
+        assert!(_validate_trade_price_X96(true, 101, 100).is_err());
+        assert!(_validate_trade_price_X96(false, 99, 100).is_err());
+    }
+}
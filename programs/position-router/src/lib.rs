@@ -1,13 +1,15 @@
 use anchor_lang::prelude::*;
+use governance::{Governance, PauseRegistry, GOVERNANCE_SEED, PAUSE_REGISTRY_SEED, PAUSE_CLOSING, PAUSE_LIQUIDITY, PAUSE_OPENING, TIMELOCK_AUTHORITY_SEED};
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnT");
-const GOVERNOR_PUBKEY: Pubkey = Pubkey::new_from_array([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32]);
 use router::cpi::accounts::PluginTransfer;
-use router::cpi::accounts::LiquidityPosition;
-use router::cpi::accounts::RiskBufferFundPosition;
-use router::cpi::accounts::PositionManagement;
+use router::cpi::accounts::{ChangeLiquidityPosition, OpenLiquidityPosition};
+use router::cpi::accounts::{DecreaseRiskBufferFundPosition, IncreaseRiskBufferFundPosition};
+use router::cpi::accounts::{DecreasePosition, IncreasePosition};
 use router::program::Router;
 use router::{self , ContractState};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{Mint, Token, TokenAccount};
 
 #[program]
 mod position_router {
@@ -17,7 +19,7 @@ mod position_router {
 
     // Constructor equivalent in Anchor
     pub fn initialize(ctx: Context<Initialize>, min_execution_fee: u128 , usd : Pubkey , router : Pubkey , min_block_delayer_executor : u128 , min_time_delay : u128 , max_time_delay : u128 , execution_gas_limit : u128 ) -> Result<()> {
-        require!(ctx.accounts.user.key() == GOVERNOR_PUBKEY, Errors::CallerUnauthorized);     
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.user, ctx.remaining_accounts)?;
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         require!(!state.initilized , Errors::AlreadyInitlized );       
         state.min_execution_fee = min_execution_fee;
@@ -33,21 +35,20 @@ mod position_router {
     }
 
     pub fn add_executor(ctx: Context<UpdateExecutor>, new_executor: Pubkey) -> Result<()> {
-        require!(ctx.accounts.user.key() == GOVERNOR_PUBKEY, Errors::CallerUnauthorized);
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.user, ctx.remaining_accounts)?;
         let governance_state: &mut Account<'_, State> = &mut ctx.accounts.state;
         governance_state.executors.push(new_executor);
         Ok(())
     }
 
     pub fn remove_executor(ctx: Context<UpdateExecutor>, executor: Pubkey) -> Result<()> {
-            require!(ctx.accounts.user.key() == GOVERNOR_PUBKEY, Errors::CallerUnauthorized);
+            governance::require_governor(&ctx.accounts.governance, &ctx.accounts.user, ctx.remaining_accounts)?;
             let address_list = &mut ctx.accounts.state.executors;
             address_list.retain(|&x| x != executor);
             Ok(())
         }
 
     pub fn update_delay_values(ctx: Context<UpdateDelayValues> , min_block_delayer_executor : u128 , min_time_delay :u128, max_time_delay : u128 ) -> Result<()> {
-        require!(ctx.accounts.user.key() == GOVERNOR_PUBKEY, Errors::CallerUnauthorized);
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         state.min_block_delayer_executor = min_block_delayer_executor;
         state.min_time_delay = min_time_delay;
@@ -58,7 +59,6 @@ mod position_router {
     // Function to update minimum execution fee
     pub fn update_min_execution_fee(ctx: Context<UpdateMinExecutionFee>, new_fee: u128) -> Result<()> {
         // Logic to update minimum execution fee
-        require!(ctx.accounts.user.key() == GOVERNOR_PUBKEY, Errors::CallerUnauthorized);
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         state.min_execution_fee = new_fee;
         emit!(MinExecutionFeeUpdated{
@@ -69,7 +69,6 @@ mod position_router {
 
     // Function to update execution gas limit
     pub fn update_execution_gas_limit(ctx: Context<UpdateExecutionGasLimit>, new_gas_limt : u128 ) -> Result<()> {
-                require!(ctx.accounts.user.key() == GOVERNOR_PUBKEY, Errors::CallerUnauthorized);
                 let state: &mut Account<'_, State> =&mut ctx.accounts.state;
                 state.execution_gas_limit = new_gas_limt;
         Ok(())
@@ -77,6 +76,7 @@ mod position_router {
 
     // Function to create open liquidity position
     pub fn create_open_liquidity_position(ctx: Context<CreateOpenLiquidityPosition>, pool: Pubkey , margin : u128 , liquidity : u128 , value : u128  ) -> Result<u128> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
         // Logic to create open liquidity position
         // msg.value check 
         // external call to router 
@@ -84,19 +84,23 @@ mod position_router {
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         
         require!(state.min_execution_fee > value , Errors::InsufficientExecutionFee);
-        // require!(ctx.accounts.signer.key() == GOVERNOR_PUBKEY, Errors::CallerUnauthorized);
         let user: AccountInfo<'_> = ctx.accounts.user.clone();
         let signer: Signer<'_>= ctx.accounts.signer.clone();
 
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
         let cpi_accounts: PluginTransfer<'_> = PluginTransfer{
-            state : ctx.accounts.router_program.to_account_info(),
+            state : ctx.accounts.router_state.to_account_info(),
             authorized_account : user.clone(),
-            user : user.clone()
+            user : user.clone(),
+            from_token : ctx.accounts.user_token_account.to_account_info(),
+            to_token : ctx.accounts.vault.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
         };
         let cpi_ctx: CpiContext<'_, '_, '_, '_, PluginTransfer<'_>> = CpiContext::new(cpi_program, cpi_accounts);
-        let to:Pubkey = Pubkey::default(); // add the program pubkey here when you will be deploying this program  
-        router::cpi::plugin_transfer(cpi_ctx , margin , signer.key() ,to  );
+        let to:Pubkey = ctx.accounts.vault.owner;
+        router::cpi::plugin_transfer(cpi_ctx , margin , signer.key() ,to  )?;
         let clock: Clock = Clock::get().unwrap();
         let clock2: Clock = Clock::get()?;
         let position: OpenLiquidityPositionRequest = OpenLiquidityPositionRequest {
@@ -139,8 +143,8 @@ mod position_router {
         Ok(true)
     }
 
-    pub fn execute_open_liquidity_position(
-        ctx: Context<CreateOpenLiquidityPosition>,
+    pub fn execute_open_liquidity_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteOpenLiquidityPosition<'info>>,
         index: usize,
         execution_fee_receiver: Pubkey,
     ) -> Result<bool> {
@@ -158,16 +162,34 @@ mod position_router {
             return Ok(false);
         }
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts: LiquidityPosition<'_> = LiquidityPosition{
-            state : ctx.accounts.router_program.to_account_info(),
-            authorized_account : user.clone(),
-            user : user.clone()
+        let position: &OpenLiquidityPositionRequest = state.open_liquidity_position_requests.get(index).unwrap();
+        require!(ctx.accounts.account.key() == position.account , Errors::InvalidOperation);
+        // the router mints the position NFT to the requesting account
+        let cpi_accounts: OpenLiquidityPosition<'_> = OpenLiquidityPosition{
+            authorized_account : ctx.accounts.account.to_account_info(),
+            state : ctx.accounts.router_state.to_account_info(),
+            user : user.clone(),
+            payer : ctx.accounts.signer.to_account_info(),
+            position_mint : ctx.accounts.position_mint.to_account_info(),
+            holder_token : ctx.accounts.holder_token.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+            associated_token_program : ctx.accounts.associated_token_program.to_account_info(),
+            system_program : ctx.accounts.system_program.to_account_info(),
+            rent : ctx.accounts.rent.to_account_info(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
         };
-        let position: &CloseLiquidityPositionRequest = state.close_liquidity_position_requests.get(index).unwrap();
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, LiquidityPosition<'_>> = CpiContext::new(cpi_program, cpi_accounts);
+        let cpi_ctx: CpiContext<'_, '_, '_, 'info, OpenLiquidityPosition<'info>> = CpiContext::new(cpi_program, cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
 
-        let receiver : Pubkey = Pubkey::default();
-        router::cpi::plugin_close_liquidity_position(cpi_ctx ,  position.positionID , receiver, position.pool );
+        router::cpi::plugin_open_liquidity_position(cpi_ctx , position.account , position.margin , position.liquidity , position.pool )?;
         state.open_liquidity_position_requests.remove(index.try_into().unwrap());
         emit!(OpenLiquidityPositionExecuted{index : index as u128 , 
             reciever : execution_fee_receiver});
@@ -175,23 +197,25 @@ mod position_router {
     }
 
     pub fn create_close_liquidity_position(
-        ctx: Context<CreateOpenLiquidityPosition>,
+        ctx: Context<LiquidityPositionRequest>,
         pool: Pubkey,
         position_id: u128,
         receiver: Pubkey,
         value : u128 
     ) -> Result<u128> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
         // Logic to create open liquidity position
         // msg.value check 
         // external call to router 
         
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         require!(state.min_execution_fee > value , Errors::InsufficientExecutionFee);
+        _require_liquidity_position_holder(&ctx.accounts.position_mint, &ctx.accounts.holder_token, pool, position_id, ctx.accounts.signer.key())?;
 
         let clock: Clock = Clock::get().unwrap();
         let clock2: Clock = Clock::get()?;
         let position: CloseLiquidityPositionRequest = CloseLiquidityPositionRequest {
-           account :  ctx.accounts.user.key(),
+           account :  ctx.accounts.signer.key(),
             pool : pool,
             positionID : position_id , 
             blockNumber : clock.slot as u128 ,
@@ -202,7 +226,7 @@ mod position_router {
         };
         let positions = &mut state.close_liquidity_position_requests;
         positions.push(position);
-        emit!(CloseLiquidityPositionRequestEvent{    account :  ctx.accounts.user.key(),
+        emit!(CloseLiquidityPositionRequestEvent{    account :  ctx.accounts.signer.key(),
             pool : pool,
             blockNumber : clock.slot as u128,
 
@@ -235,8 +259,8 @@ mod position_router {
         Ok(true)
     }
 
-    pub fn execute_close_liquidity_position(
-        ctx: Context<CreateOpenLiquidityPosition>,
+    pub fn execute_close_liquidity_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteLiquidityPositionRequest<'info>>,
         index: usize,
         execution_fee_receiver: Pubkey,
     ) -> Result<bool> {
@@ -256,15 +280,29 @@ mod position_router {
             return Ok(false);   
         }
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts: LiquidityPosition<'_> = LiquidityPosition{
-            state : ctx.accounts.router_program.to_account_info(),
+        let cpi_accounts = ChangeLiquidityPosition{
+            state : ctx.accounts.router_state.to_account_info(),
             authorized_account : user.clone(),
-            user : user.clone()
+            user : user.clone(),
+            position_mint : ctx.accounts.position_mint.to_account_info(),
+            holder_token : ctx.accounts.holder_token.to_account_info(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
         };
         let position: &CloseLiquidityPositionRequest  = state.close_liquidity_position_requests.get(index).unwrap();
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, LiquidityPosition<'_>> = CpiContext::new(cpi_program, cpi_accounts);
+        let cpi_ctx: CpiContext<'_, '_, '_, 'info, ChangeLiquidityPosition<'info>> = CpiContext::new(cpi_program, cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
     
-        router::cpi::plugin_close_liquidity_position(cpi_ctx ,position.positionID , position.receiver , position.pool );
+        router::cpi::plugin_close_liquidity_position(cpi_ctx ,position.positionID , position.receiver , position.pool )?;
         state.close_liquidity_position_requests.remove(index.try_into().unwrap());
         emit!(CloseLiquidityPositionCancelled{index : index as u128 , 
             receiver : execution_fee_receiver});
@@ -272,17 +310,19 @@ mod position_router {
     }
 
     pub fn create_adjust_liquidity_position_margin(
-        ctx: Context<CreateOpenLiquidityPosition>,
+        ctx: Context<LiquidityPositionRequest>,
         pool: Pubkey, 
         position_id: u128, 
-        margin_delta: u128, 
+        margin_delta: i128, 
         receiver: Pubkey,
         value : u128 
     ) -> Result<u128> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
             // Logic to create open liquidity position
         // msg.value check 
         // external call to router 
 
+        _require_liquidity_position_holder(&ctx.accounts.position_mint, &ctx.accounts.holder_token, pool, position_id, ctx.accounts.signer.key())?;
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         let clock: Clock = Clock::get().unwrap();
         let clock2: Clock = Clock::get()?;
@@ -292,16 +332,21 @@ mod position_router {
             let signer: Signer<'_>= ctx.accounts.signer.clone();
             let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
             let cpi_accounts: PluginTransfer<'_> = PluginTransfer{
-                state : ctx.accounts.router_program.to_account_info(),
+                state : ctx.accounts.router_state.to_account_info(),
                 authorized_account : user.clone(),
-                user : user.clone()
+                user : user.clone(),
+                from_token : ctx.accounts.user_token_account.to_account_info(),
+                to_token : ctx.accounts.vault.to_account_info(),
+                router_authority : ctx.accounts.router_authority.to_account_info(),
+                token_program : ctx.accounts.token_program.to_account_info(),
+                plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
             };
             let cpi_ctx: CpiContext<'_, '_, '_, '_, PluginTransfer<'_>> = CpiContext::new(cpi_program, cpi_accounts);
-            let to:Pubkey = Pubkey::default(); // add the program pubkey here when you will be deploying this program  
-            router::cpi::plugin_transfer(cpi_ctx , margin_delta , signer.key() ,to  );
+            let to:Pubkey = ctx.accounts.vault.owner;
+            router::cpi::plugin_transfer(cpi_ctx , margin_delta.unsigned_abs() , signer.key() ,to  )?;
         }
         let position: AdjustLiquidityPositionMarginRequest = AdjustLiquidityPositionMarginRequest {
-           account :  ctx.accounts.user.key(),
+           account :  ctx.accounts.signer.key(),
             pool : pool,
             blockNumber : clock.slot as u128,
             executionFee : value , 
@@ -366,8 +411,8 @@ mod position_router {
 
     }
     
-    pub fn execute_adjust_liquidity_position_margin(
-        ctx: Context<CreateOpenLiquidityPosition>,
+    pub fn execute_adjust_liquidity_position_margin<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteLiquidityPositionRequest<'info>>,
         index: usize,
         execution_fee_receiver : Pubkey  
     ) -> Result<bool> {
@@ -397,16 +442,28 @@ mod position_router {
             msg!("Position at index {} does not exist.", index);
         }
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts: LiquidityPosition<'_> = LiquidityPosition{
-            state : ctx.accounts.router_program.to_account_info(),
+        let cpi_accounts = ChangeLiquidityPosition{
+            state : ctx.accounts.router_state.to_account_info(),
             authorized_account : user.clone(),
-            user : user.clone()
+            user : user.clone(),
+            position_mint : ctx.accounts.position_mint.to_account_info(),
+            holder_token : ctx.accounts.holder_token.to_account_info(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
         };
         let position: &AdjustLiquidityPositionMarginRequest = state.adjust_liquidity_position_margin_requests.get(index).unwrap();
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, LiquidityPosition<'_>> = CpiContext::new(cpi_program, cpi_accounts);
-
-        let receiver : Pubkey = Pubkey::default();
-        router::cpi::plugin_adjust_liquidity_position_margin(cpi_ctx , position.pool,  position.positionID , position.margin_delta, receiver );
+        let cpi_ctx: CpiContext<'_, '_, '_, 'info, ChangeLiquidityPosition<'info>> = CpiContext::new(cpi_program, cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        router::cpi::plugin_adjust_liquidity_position_margin(cpi_ctx , position.pool,  position.positionID , position.margin_delta, position.receiver )?;
         state.adjust_liquidity_position_margin_requests.remove(index.try_into().unwrap());
         emit!(AdjustLiquidityPositionMarginExecuted{index : index as u128 , 
             receiver : execution_fee_receiver});
@@ -419,6 +476,7 @@ mod position_router {
         liquidity_delta: u128,
         value : u128 , 
     ) -> Result<u128> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
   // Logic to create open liquidity position
         // msg.value check 
         // external call to router
@@ -430,13 +488,18 @@ mod position_router {
         let signer: Signer<'_>= ctx.accounts.signer.clone();
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
         let cpi_accounts: PluginTransfer<'_> = PluginTransfer{
-            state : ctx.accounts.router_program.to_account_info(),
+            state : ctx.accounts.router_state.to_account_info(),
             authorized_account : user.clone(),
-            user : user.clone()
+            user : user.clone(),
+            from_token : ctx.accounts.user_token_account.to_account_info(),
+            to_token : ctx.accounts.vault.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
         };
         let cpi_ctx: CpiContext<'_, '_, '_, '_, PluginTransfer<'_>> = CpiContext::new(cpi_program, cpi_accounts);
-        let to:Pubkey = Pubkey::default(); // add the program pubkey here when you will be deploying this program  
-        router::cpi::plugin_transfer(cpi_ctx , liquidity_delta , signer.key() ,to  );
+        let to:Pubkey = ctx.accounts.vault.owner;
+        router::cpi::plugin_transfer(cpi_ctx , liquidity_delta , signer.key() ,to  )?;
         let clock: Clock = Clock::get().unwrap();
         let clock2: Clock = Clock::get()?;
         let position: IncreaseRiskBufferFundPositionRequest = IncreaseRiskBufferFundPositionRequest {
@@ -485,7 +548,7 @@ mod position_router {
     }
 
     pub fn execute_increase_risk_buffer_fund_position(
-        ctx: Context<CreateOpenLiquidityPosition>,
+        ctx: Context<ExecuteIncreaseRiskBufferFundPosition>,
         index: usize,
     ) -> Result<bool> {
      // should execute check 
@@ -508,15 +571,22 @@ mod position_router {
             msg!("Position at index {} does not exist.", index);
         }
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts: RiskBufferFundPosition<'_> = RiskBufferFundPosition{
-            state : ctx.accounts.router_program.to_account_info(),
+        let cpi_accounts: IncreaseRiskBufferFundPosition<'_> = IncreaseRiskBufferFundPosition{
+            state : ctx.accounts.router_state.to_account_info(),
             authorized_account : user.clone(),
-            user : user.clone()
+            user : user.clone(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
         };
         let position: &IncreaseRiskBufferFundPositionRequest = state.increase_risk_buffer_fund_position_request.get(index).unwrap();
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, RiskBufferFundPosition<'_>> = CpiContext::new(cpi_program, cpi_accounts);
+        let cpi_ctx: CpiContext<'_, '_, '_, '_, IncreaseRiskBufferFundPosition<'_>> = CpiContext::new(cpi_program, cpi_accounts);
 
-        router::cpi::plugin_increase_risk_buffer_fund_position(cpi_ctx ,  position.pool , position.account, position.liquidityDelta );
+        router::cpi::plugin_increase_risk_buffer_fund_position(cpi_ctx ,  position.pool , position.account, position.liquidityDelta )?;
         state.increase_risk_buffer_fund_position_request.remove(index.try_into().unwrap());
        
     
@@ -531,10 +601,9 @@ mod position_router {
         index:usize, 
         value : u128 ,
     ) -> Result<u128> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         let clock: Clock = Clock::get().unwrap();
-        let clock2: Clock = Clock::get()?;
-        let user: AccountInfo<'_> = ctx.accounts.user.clone();
         let position: DecreaseRiskBufferFundPositionRequest = DecreaseRiskBufferFundPositionRequest {
            account :  ctx.accounts.user.key(),
             pool : pool,
@@ -544,17 +613,6 @@ mod position_router {
             blockNumber : clock.slot as u128, 
             executionFee : 0 // add msg.value as the execution fee 
         };
-        let cpi_program = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts = RiskBufferFundPosition{
-            state : ctx.accounts.router_program.to_account_info(),
-            authorized_account : user.clone(),
-            user : user.clone()
-        };
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, RiskBufferFundPosition<'_>> = CpiContext::new(cpi_program, cpi_accounts);
-
-        let receiver : Pubkey = Pubkey::default();
-        router::cpi::plugin_decrease_risk_buffer_fund_position(cpi_ctx ,  position.pool , position.account, position.liquidityDelta , position.receiver );
-        
         let positions: &mut Vec<DecreaseRiskBufferFundPositionRequest> = &mut state.decrease_risk_buffer_fund_position_request;
         positions.push(position);
         Ok((positions.len() as u128).into() ) 
@@ -583,10 +641,11 @@ mod position_router {
     }
     
     pub fn execute_decrease_risk_buffer_fund_position(
-        ctx: Context<CreateOpenLiquidityPosition>,
+        ctx: Context<ExecuteDecreaseRiskBufferFundPosition>,
         index: usize,
     ) -> Result<bool> {
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
+        let user: AccountInfo<'_> = ctx.accounts.user.clone();
         let clock: Clock = Clock::get().unwrap();
         if let Some(position) = state.decrease_risk_buffer_fund_position_request.get(index) {
 
@@ -600,6 +659,25 @@ mod position_router {
         } else {
             msg!("Position at index {} does not exist.", index);
         }
+        let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
+        let cpi_accounts: DecreaseRiskBufferFundPosition<'_> = DecreaseRiskBufferFundPosition{
+            state : ctx.accounts.router_state.to_account_info(),
+            authorized_account : user.clone(),
+            user : user.clone(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
+        };
+        let position: &DecreaseRiskBufferFundPositionRequest = state.decrease_risk_buffer_fund_position_request.get(index).unwrap();
+        let cpi_ctx: CpiContext<'_, '_, '_, '_, DecreaseRiskBufferFundPosition<'_>> = CpiContext::new(cpi_program, cpi_accounts);
+
+        router::cpi::plugin_decrease_risk_buffer_fund_position(cpi_ctx ,  position.pool , position.account, position.liquidityDelta , position.receiver )?;
         state.decrease_risk_buffer_fund_position_request.remove(index.try_into().unwrap());
         Ok(true) 
     }
@@ -613,6 +691,7 @@ mod position_router {
         acceptable_trade_price_x96: u128,
         value : u128 
     ) -> Result<u128> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_OPENING)?;
        // Logic to create open liquidity position
         // external call to router 
         let value: u128 = 100;
@@ -625,13 +704,18 @@ mod position_router {
             let signer: Signer<'_>= ctx.accounts.signer.clone();
             let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
             let cpi_accounts: PluginTransfer<'_> = PluginTransfer{
-                state : ctx.accounts.router_program.to_account_info(),
+                state : ctx.accounts.router_state.to_account_info(),
                 authorized_account : user.clone(),
-                user : user.clone()
+                user : user.clone(),
+                from_token : ctx.accounts.user_token_account.to_account_info(),
+                to_token : ctx.accounts.vault.to_account_info(),
+                router_authority : ctx.accounts.router_authority.to_account_info(),
+                token_program : ctx.accounts.token_program.to_account_info(),
+                plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
             };
             let cpi_ctx: CpiContext<'_, '_, '_, '_, PluginTransfer<'_>> = CpiContext::new(cpi_program, cpi_accounts);
-            let to:Pubkey = Pubkey::default(); // add the program pubkey here when you will be deploying this program  
-            router::cpi::plugin_transfer(cpi_ctx , margin_delta , signer.key() ,to  );
+            let to:Pubkey = ctx.accounts.vault.owner;
+            router::cpi::plugin_transfer(cpi_ctx , margin_delta , signer.key() ,to  )?;
         }
         let position: IncreasePositionRequest = IncreasePositionRequest {
            account :  ctx.accounts.user.key(),
@@ -687,8 +771,8 @@ mod position_router {
       Ok(true) 
     }
 
-    pub fn execute_increase_position(
-        ctx: Context<CreateOpenLiquidityPosition>,
+    pub fn execute_increase_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ExecuteIncreasePosition<'info>>,
         index: usize,
     ) -> Result<bool> {
         let clock: Clock = Clock::get().unwrap();
@@ -714,23 +798,34 @@ mod position_router {
             msg!("Position at index {} does not exist.", index);
         }
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts = PositionManagement{
-            state : ctx.accounts.router_program.to_account_info(),
+        let cpi_accounts = IncreasePosition{
+            state : ctx.accounts.router_state.to_account_info(),
             authorized_account : user.clone(),
-            user : user.clone()
+            user : user.clone(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
         };
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, PositionManagement<'_>> = CpiContext::new(cpi_program, cpi_accounts);
+        let cpi_ctx: CpiContext<'_, '_, '_, 'info, IncreasePosition<'info>> = CpiContext::new(cpi_program, cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
 
         let receiver : Pubkey = Pubkey::default();
         let position: &mut &IncreasePositionRequest = &mut state.increase_position_request.get(index).unwrap();
 
-        router::cpi::plugin_increase_position(cpi_ctx ,  position.account, position.side , position.marginDelta , position.sizeDelta );
+        router::cpi::plugin_increase_position(cpi_ctx , position.pool , position.account, position.side , position.marginDelta , position.sizeDelta )?;
         
         state.increase_position_request.remove(index.try_into().unwrap());
         Ok(true) // Placeholder for the cancellation success status
     }
 
     pub fn create_decrease_position(ctx: Context<CreateOpenLiquidityPosition>, margin_delta: u128, size_delta: u128, acceptable_trade_price_x96: u128, receiver: Pubkey , side : bool , pool : Pubkey , value:u128) -> Result<u128> {
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_CLOSING)?;
       // Logic to create open liquidity position
         // msg.value check 
         // external call to router 
@@ -796,7 +891,7 @@ mod position_router {
       Ok(true) 
     }
 
-    pub fn execute_decrease_position(ctx: Context<CreateOpenLiquidityPosition>, index: usize, execution_fee_receiver: Pubkey) -> Result<bool> {
+    pub fn execute_decrease_position<'info>(ctx: Context<'_, '_, 'info, 'info, ExecuteDecreasePosition<'info>>, index: usize, execution_fee_receiver: Pubkey) -> Result<bool> {
         let clock: Clock = Clock::get().unwrap();
         let state: &mut Account<'_, State> =&mut ctx.accounts.state;
         let user: AccountInfo<'_> = ctx.accounts.user.clone();
@@ -825,17 +920,29 @@ mod position_router {
         }
 
         let cpi_program: AccountInfo<'_> = ctx.accounts.router_program.to_account_info();
-        let cpi_accounts = PositionManagement{
-            state : ctx.accounts.router_program.to_account_info(),
+        let cpi_accounts = DecreasePosition{
+            state : ctx.accounts.router_state.to_account_info(),
             authorized_account : user.clone(),
-            user : user.clone()
+            user : user.clone(),
+            plugin_approval : ctx.accounts.plugin_approval.to_account_info(),
+            pause_registry : ctx.accounts.pause_registry.to_account_info(),
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            pool_state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            pool_program : ctx.accounts.pool_program.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
         };
-        let cpi_ctx: CpiContext<'_, '_, '_, '_, PositionManagement<'_>> = CpiContext::new(cpi_program, cpi_accounts);
+        let cpi_ctx: CpiContext<'_, '_, '_, 'info, DecreasePosition<'info>> = CpiContext::new(cpi_program, cpi_accounts)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
 
         let receiver : Pubkey = Pubkey::default();
         let position= &mut state.decrease_position_request.get(index).unwrap();
 
-        router::cpi::plugin_decrease_position(cpi_ctx ,  position.account, position.side , position.marginDelta , position.sizeDelta , execution_fee_receiver);
+        router::cpi::plugin_decrease_position(cpi_ctx , position.pool , position.account, position.side , position.marginDelta , position.sizeDelta , execution_fee_receiver)?;
         
       state.decrease_position_request.remove(index.try_into().unwrap());
       emit!(DecreasePositionExecuted{
@@ -930,7 +1037,7 @@ pub struct AdjustLiquidityPositionMarginRequest{
     executionFee: u128,
     blockNumber: u128,
     blockTime: u128 , 
-    margin_delta : u128 , 
+    margin_delta : i128 , 
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -988,6 +1095,8 @@ pub struct Initialize<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 #[derive(Accounts)]
@@ -997,6 +1106,8 @@ pub struct UpdateExecutor<'info> {
     #[account(mut)]
     pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 // Context for UpdatePositionExecutor function
@@ -1056,7 +1167,245 @@ pub struct CreateOpenLiquidityPosition<'info> {
       /// CHECK
     pub user: AccountInfo<'info>,
     pub router_program: Program<'info , Router>,
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: delegate PDA of the router, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub user_token_account: Account<'info , TokenAccount>,
+    #[account(mut)]
+    pub vault: Account<'info , TokenAccount>,
+    pub token_program: Program<'info , Token>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteIncreasePosition<'info> {
+    #[account(mut)]
+    pub state: Account<'info, State>,
+      /// CHECK
+    pub user: AccountInfo<'info>,
+    pub router_program: Program<'info , Router>,
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: PDA the router signs pool calls with, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    /// CHECK: validated by the router
+    pub pool_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDecreasePosition<'info> {
+    #[account(mut)]
+    pub state: Account<'info, State>,
+      /// CHECK
+    pub user: AccountInfo<'info>,
+    pub router_program: Program<'info , Router>,
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: PDA the router signs pool calls with, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub receiver_token: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    /// CHECK: validated by the router
+    pub pool_program: AccountInfo<'info>,
+    pub token_program: Program<'info , Token>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteIncreaseRiskBufferFundPosition<'info> {
+    #[account(mut)]
+    pub state: Account<'info, State>,
+      /// CHECK
+    pub user: AccountInfo<'info>,
+    pub router_program: Program<'info , Router>,
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: PDA the router signs pool calls with, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the router
+    pub pool_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteDecreaseRiskBufferFundPosition<'info> {
+    #[account(mut)]
+    pub state: Account<'info, State>,
+      /// CHECK
+    pub user: AccountInfo<'info>,
+    pub router_program: Program<'info , Router>,
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: PDA the router signs pool calls with, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub receiver_token: AccountInfo<'info>,
+    pub token_program: Program<'info , Token>,
+    /// CHECK: validated by the router
+    pub pool_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidityPositionRequest<'info> {
+    #[account(mut)]
+    pub state: Account<'info, State>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+      /// CHECK
+    pub user: AccountInfo<'info>,
+    pub router_program: Program<'info , Router>,
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: delegate PDA of the router, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub user_token_account: Account<'info , TokenAccount>,
+    #[account(mut)]
+    pub vault: Account<'info , TokenAccount>,
+    pub token_program: Program<'info , Token>,
+    // NFT of the liquidity position and the token account holding it
+    pub position_mint: Account<'info , Mint>,
+    pub holder_token: Account<'info , TokenAccount>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteLiquidityPositionRequest<'info> {
+    #[account(mut)]
+    pub state: Account<'info, State>,
+    #[account(signer)]
+    pub signer: Signer<'info>,
+      /// CHECK
+    pub user: AccountInfo<'info>,
+    pub router_program: Program<'info , Router>,
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: PDA the router signs pool calls with, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    // NFT of the liquidity position and the token account holding it
+    pub position_mint: Account<'info , Mint>,
+    pub holder_token: Account<'info , TokenAccount>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: validated by the router
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub receiver_token: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    pub token_program: Program<'info , Token>,
+    /// CHECK: validated by the router
+    pub pool_program: AccountInfo<'info>,
+}
 
+#[derive(Accounts)]
+pub struct ExecuteOpenLiquidityPosition<'info> {
+    #[account(mut)]
+    pub state: Account<'info, State>,
+    #[account(mut, signer)]
+    pub signer: Signer<'info>,
+      /// CHECK
+    pub user: AccountInfo<'info>,
+    /// CHECK: account the request was created for, receiving the position NFT
+    pub account: AccountInfo<'info>,
+    pub router_program: Program<'info , Router>,
+    #[account(mut)]
+    pub router_state: Account<'info , ContractState>,
+    /// CHECK: mint authority PDA of the router, validated by the router
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: position NFT mint initialized by the router
+    #[account(mut)]
+    pub position_mint: AccountInfo<'info>,
+    /// CHECK: associated token account initialized by the router
+    #[account(mut)]
+    pub holder_token: AccountInfo<'info>,
+    pub token_program: Program<'info , Token>,
+    pub associated_token_program: Program<'info , AssociatedToken>,
+    pub system_program: Program<'info , System>,
+    pub rent: Sysvar<'info , Rent>,
+    /// CHECK: the account's approval of this plugin, validated by the router
+    pub plugin_approval: AccountInfo<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    /// CHECK: validated by the router
+    pub pool_program: AccountInfo<'info>,
 }
 
 #[derive(Accounts)]
@@ -1065,10 +1414,9 @@ pub struct UpdateDelayValues<'info> {
     // Fields from your Solidity struct...
     #[account(mut)]
     pub state: Account<'info, State>,
-    // Include other accounts as needed, such as the signer to authorize the update
-    /// CHECK
-    #[account(signer)]
-    pub user: AccountInfo<'info>,
+    // only queued changes reach this, signed by the governance timelock once their eta passed
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump, seeds::program = governance::ID)]
+    pub timelock_authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -1077,10 +1425,9 @@ pub struct UpdateMinExecutionFee<'info> {
     // Fields from your Solidity struct...
     #[account(mut)]
     pub state: Account<'info, State>,
-    // Include other accounts as needed, such as the signer to authorize the update
-    /// CHECK
-    #[account(signer)]
-    pub user: AccountInfo<'info>,
+    // only queued changes reach this, signed by the governance timelock once their eta passed
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump, seeds::program = governance::ID)]
+    pub timelock_authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
@@ -1089,14 +1436,19 @@ pub struct UpdateExecutionGasLimit<'info> {
     // Fields from your Solidity struct...
     #[account(mut)]
     pub state: Account<'info, State>,
-    // Include other accounts as needed, such as the signer to authorize the update
-    /// CHECK
-    #[account(signer)]
-    pub user: AccountInfo<'info>,
+    // only queued changes reach this, signed by the governance timelock once their eta passed
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump, seeds::program = governance::ID)]
+    pub timelock_authority: Signer<'info>,
 }
 // ... Additional data structures as per your contract
 
 // Custom errors
+// Errors unless `account` holds the NFT of the liquidity position `position_id` in `pool`
+fn _require_liquidity_position_holder(position_mint: &Account<'_, Mint>, holder_token: &Account<'_, TokenAccount>, pool: Pubkey, position_id: u128, account: Pubkey) -> Result<()> {
+    require!(position_mint.key() == router::liquidity_position_mint(pool, position_id), Errors::InvalidOperation);
+    router::_require_liquidity_position_holder(holder_token, position_mint.key(), account)
+}
+
 #[error_code]
 pub enum Errors {
     #[msg("Unauthorized access")]
@@ -1152,7 +1504,7 @@ pub struct  AdjustLiquidityPositionMarginCreated{
     account : Pubkey,
     pool : Pubkey,
     positionID : u128,
-    marginDelta : u128,
+    marginDelta : i128,
     reciever : Pubkey,
     value : u128,
     index : u128 , 
@@ -0,0 +1,440 @@
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::ed25519_program;
+use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::sysvar::instructions::{load_current_index_checked, load_instruction_at_checked};
+use governance::{Governance, GOVERNANCE_SEED, TIMELOCK_AUTHORITY_SEED};
+pub mod oracle;
+use oracle::{OraclePrice, PriceProvider, PythAdapter, SwitchboardAdapter};
+
+declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnP");
+/// Seed of the price feed config account
+pub const PRICE_FEED_SEED: &[u8] = b"price_feed";
+/// Seed of a token's price account, followed by the token
+pub const TOKEN_PRICE_SEED: &[u8] = b"token_price";
+/// Most updaters the price feed has room for
+pub const MAX_UPDATERS: usize = 10;
+const BASIS_POINTS_DIVISOR: u128 = 10_000;
+
+#[program]
+pub mod price_feed {
+    use super::*;
+
+    pub fn initialize(ctx: Context<Initialize>, max_age: i64, max_deviation_rate: u32) -> Result<()> {
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let feed: &mut Account<'_, PriceFeed> = &mut ctx.accounts.price_feed;
+        feed.updaters = Vec::new();
+        feed.max_age = max_age;
+        feed.max_deviation_rate = max_deviation_rate;
+        Ok(())
+    }
+
+    pub fn add_updater(ctx: Context<UpdateUpdater>, updater: Pubkey) -> Result<()> {
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let updaters: &mut Vec<Pubkey> = &mut ctx.accounts.price_feed.updaters;
+        require!(updaters.len() < MAX_UPDATERS , MyError::TooManyUpdaters);
+        if !updaters.contains(&updater) {
+            updaters.push(updater);
+        }
+        Ok(())
+    }
+
+    pub fn remove_updater(ctx: Context<UpdateUpdater>, updater: Pubkey) -> Result<()> {
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        ctx.accounts.price_feed.updaters.retain(|&x| x != updater);
+        Ok(())
+    }
+
+    // Changes how old a price may be when read and how far an update may move it, through the governance timelock
+    pub fn update_config(ctx: Context<UpdateConfig>, max_age: i64, max_deviation_rate: u32) -> Result<()> {
+        let feed: &mut Account<'_, PriceFeed> = &mut ctx.accounts.price_feed;
+        feed.max_age = max_age;
+        feed.max_deviation_rate = max_deviation_rate;
+        emit!(PriceFeedConfigChanged{ max_age , max_deviation_rate });
+        Ok(())
+    }
+
+    // Creates the price account of `token`, its first update is not bound by the deviation check
+    pub fn add_token(ctx: Context<AddToken>, token: Pubkey) -> Result<()> {
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let price: &mut Account<'_, TokenPrice> = &mut ctx.accounts.token_price;
+        price.token = token;
+        price.min_price_x96 = 0;
+        price.max_price_x96 = 0;
+        price.timestamp = 0;
+        price.primary_source = PriceSource{ kind : PriceSourceKind::Protocol , account : Pubkey::default() };
+        price.fallback_source = None;
+        Ok(())
+    }
+
+    // Picks where the price of the token is read from, and what to fall back to when that source is stale or invalid
+    pub fn set_price_sources(ctx: Context<SetPriceSources>, primary_source: PriceSource, fallback_source: Option<PriceSource>) -> Result<()> {
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
+        let price: &mut Account<'_, TokenPrice> = &mut ctx.accounts.token_price;
+        price.primary_source = primary_source;
+        price.fallback_source = fallback_source;
+        emit!(PriceSourcesChanged{ token : price.token , primary_source , fallback_source });
+        Ok(())
+    }
+
+    pub fn update_price(ctx: Context<UpdatePrice>, min_price_x96: u128, max_price_x96: u128) -> Result<()> {
+        let feed: &PriceFeed = &ctx.accounts.price_feed;
+        require!(feed.updaters.contains(&ctx.accounts.updater.key()) , MyError::CallerUnauthorized);
+        let now: i64 = Clock::get()?.unix_timestamp;
+        _apply_price(feed, &mut ctx.accounts.token_price, min_price_x96, max_price_x96, now)
+    }
+
+    // Applies a batch of prices signed off-chain by an updater. The instruction right before this one must be
+    // the ed25519 program verifying the updater's signature over the serialized `prices`, and the remaining
+    // accounts are the price accounts of the batch's tokens, in order.
+    // Entries not newer than the stored price are skipped, so racing pushes of the same batch are harmless.
+    pub fn update_prices_signed<'info>(ctx: Context<'_, '_, 'info, 'info, UpdatePricesSigned<'info>>, prices: Vec<SignedPrice>) -> Result<()> {
+        let feed: &PriceFeed = &ctx.accounts.price_feed;
+        let message: Vec<u8> = prices.try_to_vec()?;
+        let updater: Pubkey = _verify_ed25519_signature(&ctx.accounts.instructions, &message)?;
+        require!(feed.updaters.contains(&updater) , MyError::CallerUnauthorized);
+        require!(ctx.remaining_accounts.len() == prices.len() , MyError::TokenMismatch);
+
+        let now: i64 = Clock::get()?.unix_timestamp;
+        for (signed, account) in prices.iter().zip(ctx.remaining_accounts.iter()) {
+            require!(account.is_writable , MyError::TokenMismatch);
+            let mut token_price: Account<'info, TokenPrice> = Account::try_from(account)?;
+            require!(token_price.token == signed.token , MyError::TokenMismatch);
+            require!(signed.timestamp <= now && now - signed.timestamp <= feed.max_age , MyError::PriceStale);
+            if signed.timestamp <= token_price.timestamp {
+                continue;
+            }
+            _apply_price(feed, &mut token_price, signed.min_price_x96, signed.max_price_x96, signed.timestamp)?;
+            token_price.exit(&crate::ID)?;
+        }
+        Ok(())
+    }
+}
+
+/// Index price of `token`, the max price when `maximize` is set and the min price otherwise.
+/// Reads the token's primary source and falls back to its fallback source if the primary is stale or not trading.
+/// `oracles` holds the external oracle accounts the sources point at, it may be empty for protocol-fed tokens.
+pub fn get_index_price(feed: &PriceFeed, price: &TokenPrice, token: Pubkey, maximize: bool, oracles: &[AccountInfo]) -> Result<u128> {
+    require!(price.token == token , MyError::TokenMismatch);
+    let now: i64 = Clock::get()?.unix_timestamp;
+    // only a stale or halted primary falls back, a missing or malformed primary account is an error
+    let read = _read_source(feed, price, &price.primary_source, oracles, now)
+        .or_else(|e| match &price.fallback_source {
+            Some(fallback) if _is_fallback_error(&e) => _read_source(feed, price, fallback, oracles, now),
+            _ => Err(e),
+        })?;
+    Ok(if maximize { read.max_price_x96 } else { read.min_price_x96 })
+}
+
+// Reads `source` and errors if its price is older than the feed's max age
+fn _read_source(feed: &PriceFeed, price: &TokenPrice, source: &PriceSource, oracles: &[AccountInfo], now: i64) -> Result<OraclePrice> {
+    let read: OraclePrice = match source.kind {
+        PriceSourceKind::Protocol => OraclePrice{
+            min_price_x96 : price.min_price_x96,
+            max_price_x96 : price.max_price_x96,
+            publish_time : price.timestamp,
+        },
+        PriceSourceKind::Pyth | PriceSourceKind::Switchboard => {
+            let account: &AccountInfo = oracles.iter().find(|a| a.key() == source.account).ok_or(MyError::InvalidOracleAccount)?;
+            let data = account.try_borrow_data()?;
+            match source.kind {
+                PriceSourceKind::Pyth => PythAdapter.read_price(&data)?,
+                _ => SwitchboardAdapter.read_price(&data)?,
+            }
+        }
+    };
+    require!(read.publish_time != 0 && now - read.publish_time <= feed.max_age , MyError::PriceStale);
+    Ok(read)
+}
+
+fn _is_fallback_error(e: &Error) -> bool {
+    *e == error!(MyError::PriceStale) || *e == error!(MyError::OracleNotTrading)
+}
+
+// Stores a price published at `timestamp` once it passes the sanity and deviation checks.
+// The deviation is only bound against a price still within the max age, so a feed left stale can recover.
+fn _apply_price(feed: &PriceFeed, price: &mut TokenPrice, min_price_x96: u128, max_price_x96: u128, timestamp: i64) -> Result<()> {
+    require!(min_price_x96 > 0 && min_price_x96 <= max_price_x96 , MyError::InvalidPrice);
+    if price.timestamp != 0 && timestamp - price.timestamp <= feed.max_age {
+        _require_within_deviation(price.min_price_x96, min_price_x96, feed.max_deviation_rate)?;
+        _require_within_deviation(price.max_price_x96, max_price_x96, feed.max_deviation_rate)?;
+    }
+    price.min_price_x96 = min_price_x96;
+    price.max_price_x96 = max_price_x96;
+    price.timestamp = timestamp;
+    emit!(PriceUpdated{
+        token : price.token,
+        min_price_x96,
+        max_price_x96,
+        timestamp,
+    });
+    Ok(())
+}
+
+// Returns the key that signed `message` in the ed25519 program instruction preceding the current one.
+// Only single-signature instructions carrying their own key, signature and message are accepted.
+fn _verify_ed25519_signature(instructions: &AccountInfo, message: &[u8]) -> Result<Pubkey> {
+    let current: u16 = load_current_index_checked(instructions)?;
+    require!(current > 0 , MyError::MissingSignature);
+    let ix: Instruction = load_instruction_at_checked(usize::from(current - 1), instructions)?;
+    require!(ix.program_id == ed25519_program::ID && ix.accounts.is_empty() , MyError::MissingSignature);
+
+    // [num_signatures u8, padding u8, then offsets of the signature, key and message as little endian u16s]
+    let data: &[u8] = &ix.data;
+    require!(data.len() >= 16 && data[0] == 1 , MyError::InvalidSignature);
+    let read = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let this_instruction: u16 = u16::MAX;
+    require!(read(4) == this_instruction && read(8) == this_instruction && read(14) == this_instruction , MyError::InvalidSignature);
+
+    let public_key_offset: usize = usize::from(read(6));
+    let message_offset: usize = usize::from(read(10));
+    let message_size: usize = usize::from(read(12));
+    let public_key: &[u8] = data.get(public_key_offset..public_key_offset + 32).ok_or(MyError::InvalidSignature)?;
+    let signed_message: &[u8] = data.get(message_offset..message_offset + message_size).ok_or(MyError::InvalidSignature)?;
+    require!(signed_message == message , MyError::InvalidSignature);
+    Ok(Pubkey::try_from(public_key).map_err(|_| MyError::InvalidSignature)?)
+}
+
+// Errors if `next` moves away from `previous` by more than `max_deviation_rate`
+fn _require_within_deviation(previous: u128, next: u128, max_deviation_rate: u32) -> Result<()> {
+    let deviation: u128 = previous.abs_diff(next);
+    let bound: u128 = previous.checked_mul(max_deviation_rate as u128).ok_or(MyError::InvalidPrice)? / BASIS_POINTS_DIVISOR;
+    require!(deviation <= bound , MyError::PriceDeviationTooLarge);
+    Ok(())
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct SignedPrice {
+    pub token : Pubkey,
+    pub min_price_x96 : u128,
+    pub max_price_x96 : u128,
+    pub timestamp : i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PriceSourceKind {
+    // prices pushed to this program by the updaters
+    Protocol,
+    Pyth,
+    Switchboard,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug)]
+pub struct PriceSource {
+    pub kind : PriceSourceKind,
+    // oracle account read for Pyth and Switchboard sources, unused for the protocol source
+    pub account : Pubkey,
+}
+
+#[account]
+pub struct PriceFeed {
+    pub updaters : Vec<Pubkey>,
+    // seconds a published price stays readable
+    pub max_age : i64,
+    // largest move of a single update, in basis points of the previous price
+    pub max_deviation_rate : u32,
+}
+
+#[account]
+pub struct TokenPrice {
+    pub token : Pubkey,
+    pub min_price_x96 : u128,
+    pub max_price_x96 : u128,
+    pub timestamp : i64,
+    pub primary_source : PriceSource,
+    pub fallback_source : Option<PriceSource>,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
+    #[account(init, payer = payer, space = 8 + 4 + 32 * MAX_UPDATERS + 8 + 4, seeds = [PRICE_FEED_SEED], bump)]
+    pub price_feed: Account<'info, PriceFeed>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateUpdater<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
+    #[account(mut, seeds = [PRICE_FEED_SEED], bump)]
+    pub price_feed: Account<'info, PriceFeed>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    // only queued changes reach this, signed by the governance timelock once their eta passed
+    #[account(seeds = [TIMELOCK_AUTHORITY_SEED], bump, seeds::program = governance::ID)]
+    pub timelock_authority: Signer<'info>,
+    #[account(mut, seeds = [PRICE_FEED_SEED], bump)]
+    pub price_feed: Account<'info, PriceFeed>,
+}
+
+#[derive(Accounts)]
+#[instruction(token: Pubkey)]
+pub struct AddToken<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
+    #[account(init, payer = payer, space = 8 + 32 + 16 + 16 + 8 + 33 + 1 + 33, seeds = [TOKEN_PRICE_SEED, token.as_ref()], bump)]
+    pub token_price: Account<'info, TokenPrice>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetPriceSources<'info> {
+    /// CHECK: checked against the governor by `require_governor`
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
+    #[account(mut, seeds = [TOKEN_PRICE_SEED, token_price.token.as_ref()], bump)]
+    pub token_price: Account<'info, TokenPrice>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePrice<'info> {
+    pub updater: Signer<'info>,
+    #[account(seeds = [PRICE_FEED_SEED], bump)]
+    pub price_feed: Account<'info, PriceFeed>,
+    #[account(mut, seeds = [TOKEN_PRICE_SEED, token_price.token.as_ref()], bump)]
+    pub token_price: Account<'info, TokenPrice>,
+}
+
+#[derive(Accounts)]
+pub struct UpdatePricesSigned<'info> {
+    #[account(seeds = [PRICE_FEED_SEED], bump)]
+    pub price_feed: Account<'info, PriceFeed>,
+    /// CHECK: the instructions sysvar, read for the ed25519 verification
+    #[account(address = anchor_lang::solana_program::sysvar::instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+}
+
+#[event]
+pub struct PriceUpdated {
+    pub token : Pubkey,
+    pub min_price_x96 : u128,
+    pub max_price_x96 : u128,
+    pub timestamp : i64,
+}
+
+#[event]
+pub struct PriceSourcesChanged {
+    pub token : Pubkey,
+    pub primary_source : PriceSource,
+    pub fallback_source : Option<PriceSource>,
+}
+
+#[event]
+pub struct PriceFeedConfigChanged {
+    pub max_age : i64,
+    pub max_deviation_rate : u32,
+}
+
+#[error_code]
+pub enum MyError {
+    #[msg("Unauthorsized caller")]
+    CallerUnauthorized,
+    #[msg("Too many updaters")]
+    TooManyUpdaters,
+    #[msg("Invalid price")]
+    InvalidPrice,
+    #[msg("Price is stale")]
+    PriceStale,
+    #[msg("Price deviates too much from the previous one")]
+    PriceDeviationTooLarge,
+    #[msg("Price account is for another token")]
+    TokenMismatch,
+    #[msg("Missing ed25519 signature verification")]
+    MissingSignature,
+    #[msg("Invalid ed25519 signature verification")]
+    InvalidSignature,
+    #[msg("Invalid oracle account")]
+    InvalidOracleAccount,
+    #[msg("Oracle is not trading")]
+    OracleNotTrading,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRICE_X96: u128 = 2000 << 96;
+
+    fn feed() -> PriceFeed {
+        PriceFeed{ updaters : Vec::new() , max_age : 60 , max_deviation_rate : 500 }
+    }
+
+    fn token_price(timestamp: i64) -> TokenPrice {
+        TokenPrice{
+            token : Pubkey::new_unique(),
+            min_price_x96 : PRICE_X96,
+            max_price_x96 : PRICE_X96,
+            timestamp,
+            primary_source : PriceSource{ kind : PriceSourceKind::Protocol , account : Pubkey::default() },
+            fallback_source : None,
+        }
+    }
+
+    #[test]
+    fn first_price_is_not_bound_by_the_deviation() {
+        let mut price = token_price(0);
+        price.min_price_x96 = 0;
+        price.max_price_x96 = 0;
+        _apply_price(&feed(), &mut price, PRICE_X96, PRICE_X96, 1_000).unwrap();
+        assert_eq!(price.min_price_x96, PRICE_X96);
+        assert_eq!(price.timestamp, 1_000);
+    }
+
+    #[test]
+    fn fresh_price_is_bound_by_the_deviation() {
+        let mut price = token_price(1_000);
+        let within = PRICE_X96 + PRICE_X96 * 500 / 10_000;
+        _apply_price(&feed(), &mut price, within, within, 1_010).unwrap();
+        assert_eq!(price.max_price_x96, within);
+
+        let mut price = token_price(1_000);
+        let beyond = within + 1;
+        assert_eq!(_apply_price(&feed(), &mut price, beyond, beyond, 1_010).unwrap_err(), error!(MyError::PriceDeviationTooLarge));
+        assert_eq!(price.max_price_x96, PRICE_X96);
+        assert_eq!(price.timestamp, 1_000);
+    }
+
+    #[test]
+    fn stale_price_is_not_bound_by_the_deviation() {
+        let mut price = token_price(1_000);
+        _apply_price(&feed(), &mut price, PRICE_X96 * 2, PRICE_X96 * 2, 1_061).unwrap();
+        assert_eq!(price.min_price_x96, PRICE_X96 * 2);
+        assert_eq!(price.timestamp, 1_061);
+
+        let mut price = token_price(1_000);
+        assert_eq!(_apply_price(&feed(), &mut price, PRICE_X96 * 2, PRICE_X96 * 2, 1_060).unwrap_err(), error!(MyError::PriceDeviationTooLarge));
+    }
+
+    #[test]
+    fn protocol_price_past_the_max_age_is_stale() {
+        let price = token_price(1_000);
+        let source = price.primary_source;
+        assert_eq!(_read_source(&feed(), &price, &source, &[], 1_060).unwrap().min_price_x96, PRICE_X96);
+        assert_eq!(_read_source(&feed(), &price, &source, &[], 1_061).unwrap_err(), error!(MyError::PriceStale));
+    }
+
+    #[test]
+    fn only_stale_or_halted_primaries_fall_back() {
+        assert!(_is_fallback_error(&error!(MyError::PriceStale)));
+        assert!(_is_fallback_error(&error!(MyError::OracleNotTrading)));
+        assert!(!_is_fallback_error(&error!(MyError::InvalidOracleAccount)));
+    }
+}
@@ -0,0 +1,218 @@
+use anchor_lang::prelude::*;
+
+use crate::MyError;
+
+/// 2^96, one in Q96
+pub const Q96: u128 = 1 << 96;
+
+/// Price band read from an oracle account, in Q96.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OraclePrice {
+    pub min_price_x96: u128,
+    pub max_price_x96: u128,
+    pub publish_time: i64,
+}
+
+/// Reads a price out of the raw data of an oracle account.
+pub trait PriceProvider {
+    fn read_price(&self, data: &[u8]) -> Result<OraclePrice>;
+}
+
+/// Pyth v2 price account. The band is the aggregate price plus or minus its confidence interval.
+pub struct PythAdapter;
+
+const PYTH_MAGIC: u32 = 0xa1b2c3d4;
+const PYTH_VERSION: u32 = 2;
+const PYTH_PRICE_ACCOUNT: u32 = 3;
+const PYTH_STATUS_TRADING: u32 = 1;
+// offsets into the price account
+const PYTH_EXPONENT: usize = 20;
+const PYTH_TIMESTAMP: usize = 96;
+const PYTH_AGGREGATE_PRICE: usize = 208;
+const PYTH_AGGREGATE_CONF: usize = 216;
+const PYTH_AGGREGATE_STATUS: usize = 224;
+const PYTH_MIN_LEN: usize = 240;
+
+impl PriceProvider for PythAdapter {
+    fn read_price(&self, data: &[u8]) -> Result<OraclePrice> {
+        require!(data.len() >= PYTH_MIN_LEN , MyError::InvalidOracleAccount);
+        require!(read_u32(data, 0) == PYTH_MAGIC && read_u32(data, 4) == PYTH_VERSION && read_u32(data, 8) == PYTH_PRICE_ACCOUNT , MyError::InvalidOracleAccount);
+        require!(read_u32(data, PYTH_AGGREGATE_STATUS) == PYTH_STATUS_TRADING , MyError::OracleNotTrading);
+
+        let price: i64 = read_i64(data, PYTH_AGGREGATE_PRICE);
+        let conf: u64 = read_u64(data, PYTH_AGGREGATE_CONF);
+        require!(price > 0 && (conf as i128) < (price as i128) , MyError::InvalidPrice);
+        let exponent: i32 = read_i32(data, PYTH_EXPONENT);
+        Ok(OraclePrice{
+            min_price_x96 : to_q96(price as u128 - conf as u128, exponent)?,
+            max_price_x96 : to_q96(price as u128 + conf as u128, exponent)?,
+            publish_time : read_i64(data, PYTH_TIMESTAMP),
+        })
+    }
+}
+
+/// Switchboard v2 aggregator account. The band is the latest confirmed result plus or minus its standard deviation.
+pub struct SwitchboardAdapter;
+
+const SWITCHBOARD_AGGREGATOR_DISCRIMINATOR: [u8; 8] = [217, 230, 65, 101, 201, 162, 27, 125];
+// offsets into the packed aggregator account, within its latest confirmed round
+const SWITCHBOARD_ROUND_OPEN_TIMESTAMP: usize = 358;
+const SWITCHBOARD_RESULT_MANTISSA: usize = 366;
+const SWITCHBOARD_RESULT_SCALE: usize = 382;
+const SWITCHBOARD_STD_DEVIATION_MANTISSA: usize = 386;
+const SWITCHBOARD_STD_DEVIATION_SCALE: usize = 402;
+const SWITCHBOARD_MIN_LEN: usize = 406;
+
+impl PriceProvider for SwitchboardAdapter {
+    fn read_price(&self, data: &[u8]) -> Result<OraclePrice> {
+        require!(data.len() >= SWITCHBOARD_MIN_LEN , MyError::InvalidOracleAccount);
+        require!(data[..8] == SWITCHBOARD_AGGREGATOR_DISCRIMINATOR , MyError::InvalidOracleAccount);
+
+        let result: i128 = read_i128(data, SWITCHBOARD_RESULT_MANTISSA);
+        let result_scale: u32 = read_u32(data, SWITCHBOARD_RESULT_SCALE);
+        let std_deviation: i128 = read_i128(data, SWITCHBOARD_STD_DEVIATION_MANTISSA);
+        let std_deviation_scale: u32 = read_u32(data, SWITCHBOARD_STD_DEVIATION_SCALE);
+        require!(result > 0 && std_deviation >= 0 , MyError::InvalidPrice);
+
+        let price_x96: u128 = to_q96(result as u128, -(result_scale as i32))?;
+        let conf_x96: u128 = to_q96(std_deviation as u128, -(std_deviation_scale as i32))?;
+        require!(conf_x96 < price_x96 , MyError::InvalidPrice);
+        Ok(OraclePrice{
+            min_price_x96 : price_x96 - conf_x96,
+            max_price_x96 : price_x96.checked_add(conf_x96).ok_or(MyError::InvalidPrice)?,
+            publish_time : read_i64(data, SWITCHBOARD_ROUND_OPEN_TIMESTAMP),
+        })
+    }
+}
+
+/// Converts `value * 10^exponent` to Q96, rounding down.
+pub fn to_q96(value: u128, exponent: i32) -> Result<u128> {
+    let scale: u128 = 10u128.checked_pow(exponent.unsigned_abs()).ok_or(MyError::InvalidPrice)?;
+    if exponent >= 0 {
+        return value.checked_mul(scale).and_then(|v| v.checked_mul(Q96)).ok_or(error!(MyError::InvalidPrice));
+    }
+    shl96_div(value, scale).ok_or(error!(MyError::InvalidPrice))
+}
+
+// (value << 96) / denominator by long division, as the shifted value does not fit in 128 bits
+fn shl96_div(value: u128, denominator: u128) -> Option<u128> {
+    let mut quotient: u128 = 0;
+    let mut remainder: u128 = 0;
+    for i in (0..128 + 96).rev() {
+        let bit: u128 = if i >= 96 { (value >> (i - 96)) & 1 } else { 0 };
+        // remainder < denominator <= 10^38 < 2^127, so the shift never overflows
+        remainder = (remainder << 1) | bit;
+        quotient = quotient.checked_mul(2)?;
+        if remainder >= denominator {
+            remainder -= denominator;
+            quotient |= 1;
+        }
+    }
+    Some(quotient)
+}
+
+fn read_u32(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn read_i32(data: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes(data[at..at + 4].try_into().unwrap())
+}
+
+fn read_u64(data: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+fn read_i64(data: &[u8], at: usize) -> i64 {
+    i64::from_le_bytes(data[at..at + 8].try_into().unwrap())
+}
+
+fn read_i128(data: &[u8], at: usize) -> i128 {
+    i128::from_le_bytes(data[at..at + 16].try_into().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pyth_fixture(price: i64, conf: u64, exponent: i32, status: u32, timestamp: i64) -> Vec<u8> {
+        let mut data = vec![0u8; 3312];
+        data[0..4].copy_from_slice(&PYTH_MAGIC.to_le_bytes());
+        data[4..8].copy_from_slice(&PYTH_VERSION.to_le_bytes());
+        data[8..12].copy_from_slice(&PYTH_PRICE_ACCOUNT.to_le_bytes());
+        data[PYTH_EXPONENT..PYTH_EXPONENT + 4].copy_from_slice(&exponent.to_le_bytes());
+        data[PYTH_TIMESTAMP..PYTH_TIMESTAMP + 8].copy_from_slice(&timestamp.to_le_bytes());
+        data[PYTH_AGGREGATE_PRICE..PYTH_AGGREGATE_PRICE + 8].copy_from_slice(&price.to_le_bytes());
+        data[PYTH_AGGREGATE_CONF..PYTH_AGGREGATE_CONF + 8].copy_from_slice(&conf.to_le_bytes());
+        data[PYTH_AGGREGATE_STATUS..PYTH_AGGREGATE_STATUS + 4].copy_from_slice(&status.to_le_bytes());
+        data
+    }
+
+    fn switchboard_fixture(result: i128, result_scale: u32, std_deviation: i128, std_deviation_scale: u32, timestamp: i64) -> Vec<u8> {
+        let mut data = vec![0u8; 3851];
+        data[..8].copy_from_slice(&SWITCHBOARD_AGGREGATOR_DISCRIMINATOR);
+        data[SWITCHBOARD_ROUND_OPEN_TIMESTAMP..SWITCHBOARD_ROUND_OPEN_TIMESTAMP + 8].copy_from_slice(&timestamp.to_le_bytes());
+        data[SWITCHBOARD_RESULT_MANTISSA..SWITCHBOARD_RESULT_MANTISSA + 16].copy_from_slice(&result.to_le_bytes());
+        data[SWITCHBOARD_RESULT_SCALE..SWITCHBOARD_RESULT_SCALE + 4].copy_from_slice(&result_scale.to_le_bytes());
+        data[SWITCHBOARD_STD_DEVIATION_MANTISSA..SWITCHBOARD_STD_DEVIATION_MANTISSA + 16].copy_from_slice(&std_deviation.to_le_bytes());
+        data[SWITCHBOARD_STD_DEVIATION_SCALE..SWITCHBOARD_STD_DEVIATION_SCALE + 4].copy_from_slice(&std_deviation_scale.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn to_q96_handles_exponents() {
+        assert_eq!(to_q96(3, 0).unwrap(), 3 * Q96);
+        assert_eq!(to_q96(3, 2).unwrap(), 300 * Q96);
+        assert_eq!(to_q96(150, -2).unwrap(), 3 * Q96 / 2);
+        assert_eq!(to_q96(1, -30).unwrap(), Q96 / 10u128.pow(30));
+        assert!(to_q96(u128::MAX, 0).is_err());
+    }
+
+    #[test]
+    fn pyth_reads_the_confidence_band() {
+        // 2500.00000000 +- 1.50000000
+        let data = pyth_fixture(250_000_000_000, 150_000_000, -8, PYTH_STATUS_TRADING, 1_700_000_000);
+        let price = PythAdapter.read_price(&data).unwrap();
+        assert_eq!(price.min_price_x96, to_q96(249_850_000_000, -8).unwrap());
+        assert_eq!(price.max_price_x96, to_q96(250_150_000_000, -8).unwrap());
+        assert_eq!(price.max_price_x96, 250_150 * Q96 / 100);
+        assert_eq!(price.publish_time, 1_700_000_000);
+    }
+
+    #[test]
+    fn pyth_rejects_bad_accounts() {
+        let halted = pyth_fixture(250_000_000_000, 150_000_000, -8, 0, 1_700_000_000);
+        assert!(PythAdapter.read_price(&halted).is_err());
+
+        let mut wrong_magic = pyth_fixture(250_000_000_000, 150_000_000, -8, PYTH_STATUS_TRADING, 1_700_000_000);
+        wrong_magic[0] = 0;
+        assert!(PythAdapter.read_price(&wrong_magic).is_err());
+
+        let too_uncertain = pyth_fixture(100, 100, -8, PYTH_STATUS_TRADING, 1_700_000_000);
+        assert!(PythAdapter.read_price(&too_uncertain).is_err());
+
+        assert!(PythAdapter.read_price(&[0u8; 100]).is_err());
+    }
+
+    #[test]
+    fn switchboard_reads_the_std_deviation_band() {
+        // 42.5 +- 0.25
+        let data = switchboard_fixture(425, 1, 25, 2, 1_700_000_100);
+        let price = SwitchboardAdapter.read_price(&data).unwrap();
+        assert_eq!(price.min_price_x96, 4225 * Q96 / 100);
+        assert_eq!(price.max_price_x96, 4275 * Q96 / 100);
+        assert_eq!(price.publish_time, 1_700_000_100);
+    }
+
+    #[test]
+    fn switchboard_rejects_bad_accounts() {
+        let mut wrong_discriminator = switchboard_fixture(425, 1, 25, 2, 1_700_000_100);
+        wrong_discriminator[0] = 0;
+        assert!(SwitchboardAdapter.read_price(&wrong_discriminator).is_err());
+
+        let negative = switchboard_fixture(-425, 1, 25, 2, 1_700_000_100);
+        assert!(SwitchboardAdapter.read_price(&negative).is_err());
+
+        assert!(SwitchboardAdapter.read_price(&[0u8; 300]).is_err());
+    }
+}
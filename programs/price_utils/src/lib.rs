@@ -1,7 +1,15 @@
 use anchor_lang::prelude::*;
 
+pub mod math;
+use math::{mul_div, mul_div2, mul_div_up, Q64, Q96, U256};
+
 declare_id!("3zdpR6aw81LKw7GQpDboSMU9e4aaB3MC6KoUr4kBSWQT");
 
+/// Index of the last of the price vertices
+pub const LATEST_VERTEX: u8 = 9;
+/// Denominator of the balance and premium rates of the vertex configs
+pub const BASIS_POINTS_DIVISOR: u128 = 10_000;
+
 
 #[program]
 pub mod price_util {
@@ -16,20 +24,139 @@ pub mod price_util {
     // Additional functions can be added here
 }
 
-pub fn calculate_premium_price_after(step : &mut SimulateMoveStep , reached : bool , size_usd : u128 ) -> (u128){
-    
+pub fn calculate_premium_price_after(step: &SimulateMoveStep, reached: bool, size_used: u128) -> u128 {
     if reached {
         return step.to.premium_rate_x96;
     }
 
+    let size_after = if step.improve_balance {
+        step.current.size - size_used
+    } else {
+        step.current.size + size_used
+    };
+    calculate_premium_rate_x96(&step.from, &step.to, size_after)
+}
+
+// The premium rate at `size` on the segment between two vertices, rounded up
+pub fn calculate_premium_rate_x96(from: &PriceVertex, to: &PriceVertex, size: u128) -> u128 {
+    let (lower, upper) = if from.size <= to.size { (from, to) } else { (to, from) };
+    if upper.size == lower.size {
+        return upper.premium_rate_x96;
+    }
+
+    lower.premium_rate_x96 + mul_div_up(
+        upper.premium_rate_x96 - lower.premium_rate_x96,
+        size - lower.size,
+        upper.size - lower.size,
+    )
+}
+
+/// Moves the premium rate along the price vertices for a trade of `parameter.size_delta` and
+/// returns the size-weighted trade price. Net sizes beyond the liquidation vertex only fit in its
+/// liquidation buffer, which only liquidations may grow.
+pub fn update_price_state(global_position: &mut GlobalLiquidityPosition, price_state: &mut PriceState, price_config: &MarketPriceConfig, parameter: &UpdatePriceStateParameter) -> Result<u128> {
+    let price_move = move_price_state(global_position, price_state, price_config, parameter, true)?;
+    emit!(PriceStateChanged{
+        market : parameter.market,
+        side : parameter.side,
+        size_delta : parameter.size_delta,
+        trade_price_x96 : price_move.trade_price_x96,
+        premium_rate_after_x96 : price_state.premium_rate_x96,
+        current_vertex_index : price_state.current_vertex_index,
+        basis_index_price_x96 : price_state.basis_index_price_x96,
+        global_side : global_position.side,
+        net_size_after : global_position.net_size,
+        liquidation_buffer_net_size_after : global_position.liquidation_buffer_net_size,
+    });
+    Ok(price_move.trade_price_x96)
+}
+
+/// Marks the LP net position, liquidation buffers included, from the previous settlement point price to
+/// `price_x96` and accrues the PnL to the unrealized PnL growth of the liquidity, then makes `price_x96`
+/// the settlement point price.
+pub fn settle_liquidity_unrealized_pnl(global_position: &mut GlobalLiquidityPosition, price_x96: u128) {
+    let net_size = global_position.net_size + global_position.liquidation_buffer_net_size;
+    if net_size > 0 && global_position.liquidity > 0 {
+        let unrealized_pnl = calculate_unrealized_pnl(global_position.side, net_size, global_position.previous_sp_price_x96, price_x96);
+        let unrealized_pnl_growth_delta_x64 = if unrealized_pnl >= 0 {
+            mul_div(unrealized_pnl as u128, Q64, global_position.liquidity) as i128
+        } else {
+            -(mul_div_up(unrealized_pnl.unsigned_abs(), Q64, global_position.liquidity) as i128)
+        };
+        global_position.unrealized_pnl_growth_x64 += unrealized_pnl_growth_delta_x64;
+    }
+    global_position.previous_sp_price_x96 = price_x96;
+}
+
+// PnL of a `side` position of `size` entered at `entry_price_x96`, rounded against the holder
+pub fn calculate_unrealized_pnl(side: bool, size: u128, entry_price_x96: u128, price_x96: u128) -> i128 {
+    let gained = if side { price_x96 >= entry_price_x96 } else { price_x96 <= entry_price_x96 };
+    let price_delta_x96 = price_x96.abs_diff(entry_price_x96);
+    if gained {
+        mul_div(size, price_delta_x96, Q96) as i128
+    } else {
+        -(mul_div_up(size, price_delta_x96, Q96) as i128)
+    }
+}
 
-    return 100;
+/// The prices a long and a short trade of no size would get, from the index prices each of them
+/// trades at.
+pub fn get_market_price(price_state: &PriceState, global_position: &GlobalLiquidityPosition, long_index_price_x96: u128, short_index_price_x96: u128) -> MarketPrice {
+    MarketPrice {
+        long_market_price_x96: calculate_market_price_x96(global_position.side, true, long_index_price_x96, price_state.basis_index_price_x96, price_state.premium_rate_x96),
+        short_market_price_x96: calculate_market_price_x96(global_position.side, false, short_index_price_x96, price_state.basis_index_price_x96, price_state.premium_rate_x96),
+        basis_index_price_x96: price_state.basis_index_price_x96,
+        premium_rate_x96: price_state.premium_rate_x96,
+    }
 }
 
-pub fn update_price_state( parameter: UpdatePriceStateParameter ,  global_position : GlobalLiquidityPosition , mut price_state : PriceState) -> Result<()> {
+/// Simulates a trade of `size_delta` on copies of the price state and of the LP net position,
+/// returning its price without touching either.
+pub fn quote_trade(
+    price_state: &PriceState,
+    global_position: &GlobalLiquidityPosition,
+    price_config: &MarketPriceConfig,
+    trading_fee_rate: u32,
+    side: bool,
+    size_delta: u128,
+    index_price_x96: u128,
+) -> Result<TradeQuote> {
+    require!(index_price_x96 > 0 , Errors::InvalidOperation);
+    let mut price_state = price_state.clone();
+    let mut global_position = global_position.clone();
+    let parameter = UpdatePriceStateParameter {
+        market: Pubkey::default(),
+        side,
+        size_delta,
+        index_price_x96,
+        liquidation_vertex_index: price_config.liquidation_vertex_index,
+        liquidation: false,
+    };
+    let price_move = move_price_state(&mut global_position, &mut price_state, price_config, &parameter, false)?;
+
+    let trade_price_x96 = price_move.trade_price_x96;
+    // positive when the trader gets a worse price than the index price
+    let worse = if side { trade_price_x96 >= index_price_x96 } else { trade_price_x96 <= index_price_x96 };
+    let price_impact_rate = mul_div(trade_price_x96.abs_diff(index_price_x96), BASIS_POINTS_DIVISOR, index_price_x96);
+    let price_impact_rate = i32::try_from(price_impact_rate).map_err(|_| Errors::InvalidOperation)?;
+    let trading_fee = mul_div_up(mul_div(size_delta, trade_price_x96, Q96), trading_fee_rate as u128, BASIS_POINTS_DIVISOR);
+
+    Ok(TradeQuote {
+        trade_price_x96,
+        price_impact_rate: if worse { price_impact_rate } else { -price_impact_rate },
+        premium_rate_after_x96: price_state.premium_rate_x96,
+        vertices_crossed: price_move.vertices_crossed,
+        trading_fee,
+        liquidation_buffer_used: price_move.liquidation_buffer_used > 0,
+    })
+}
+
+// Applies a trade to the price state and the LP net position, emitting the vertex changes unless only simulating
+fn move_price_state(global_position: &mut GlobalLiquidityPosition, price_state: &mut PriceState, price_config: &MarketPriceConfig, parameter: &UpdatePriceStateParameter, emit_events: bool) -> Result<PriceStateMove> {
     if parameter.size_delta == 0 {
         return err!(Errors::InvalidOperation);
     }
+    require!((parameter.liquidation_vertex_index as usize) < price_state.price_vertices.len() , Errors::InvalidOperation);
 
     let mut global_position_cache = global_position.clone();
     let mut price_state_cache = PriceStateCache {
@@ -37,7 +164,7 @@ pub fn update_price_state( parameter: UpdatePriceStateParameter ,  global_positi
         pending_vertex_index: price_state.pending_vertex_index,
         liquidation_vertex_index: parameter.liquidation_vertex_index,
         current_vertex_index: price_state.current_vertex_index,
-        basis_index_price_x96: price_state.basis_index_price_x96 as u64,
+        basis_index_price_x96: price_state.basis_index_price_x96,
     };
 
     let balanced = (global_position_cache.net_size | global_position_cache.liquidation_buffer_net_size) == 0;
@@ -46,42 +173,137 @@ pub fn update_price_state( parameter: UpdatePriceStateParameter ,  global_positi
     }
 
     let improve_balance = parameter.side == global_position_cache.side && !balanced;
-    // Assuming `_update_price_state` is another function you'll implement
-    let (trade_price_x96_times_size_total, size_left, total_buffer_used) = self::_update_price_state(
-        &mut global_position_cache,
-        &mut price_state,
+    let (mut trade_price_x96_times_size_total, size_left, total_buffer_used, mut vertices_crossed) = _update_price_state(
+        &global_position_cache,
+        price_state,
         &mut price_state_cache,
-        &parameter,
+        parameter,
         improve_balance,
-    );
+    )?;
 
     if !improve_balance {
         global_position_cache.side = !parameter.side;
-        global_position_cache.net_size +=parameter.size_delta - total_buffer_used;
-        global_position_cache.liquidation_buffer_net_size +=total_buffer_used;
+        global_position_cache.net_size += parameter.size_delta - total_buffer_used;
+        global_position_cache.liquidation_buffer_net_size += total_buffer_used;
+    } else {
+        // the vertices deferred while the market was beyond them are rebuilt once it moves back below them
+        if price_state_cache.pending_vertex_index > price_state_cache.current_vertex_index {
+            _change_price_vertex(price_state, price_config, parameter.market, global_position_cache.liquidity, parameter.index_price_x96, price_state_cache.current_vertex_index, price_state_cache.pending_vertex_index, emit_events);
+            price_state.pending_vertex_index = price_state_cache.current_vertex_index;
+        }
+
+        global_position_cache.net_size -= parameter.size_delta - size_left - total_buffer_used;
+        global_position_cache.liquidation_buffer_net_size -= total_buffer_used;
     }
 
-    // else {
+    // the trade closed the whole LP net position, the rest opens one on the other side
+    if size_left > 0 {
+        global_position_cache.side = !global_position_cache.side;
+        price_state_cache.basis_index_price_x96 = parameter.index_price_x96;
 
-    //     if price_state_cache.pending_vertex_index > price_state_cache.current_vertex_index {
+        let parameter_left = UpdatePriceStateParameter {
+            size_delta: size_left,
+            ..parameter.clone()
+        };
+        let (trade_price_x96_times_size_total_left, _, total_buffer_used_left, vertices_crossed_left) = _update_price_state(
+            &global_position_cache,
+            price_state,
+            &mut price_state_cache,
+            &parameter_left,
+            false,
+        )?;
+
+        trade_price_x96_times_size_total += trade_price_x96_times_size_total_left;
+        vertices_crossed += vertices_crossed_left;
+        global_position_cache.net_size = size_left - total_buffer_used_left;
+        global_position_cache.liquidation_buffer_net_size = total_buffer_used_left;
+    }
 
-    //     }
-    // }
+    // longs round up and shorts round down, both against the trader
+    let trade_price_x96 = if parameter.side {
+        trade_price_x96_times_size_total.div_up(parameter.size_delta)
+    } else {
+        trade_price_x96_times_size_total.div_down(parameter.size_delta)
+    };
 
-    Ok(())
+    // the LP net position before the trade is marked to the price the trade moves it at
+    settle_liquidity_unrealized_pnl(global_position, trade_price_x96);
+    global_position.side = global_position_cache.side;
+    global_position.net_size = global_position_cache.net_size;
+    global_position.liquidation_buffer_net_size = global_position_cache.liquidation_buffer_net_size;
+    price_state.premium_rate_x96 = price_state_cache.premium_rate_x96;
+    price_state.current_vertex_index = price_state_cache.current_vertex_index;
+    price_state.basis_index_price_x96 = price_state_cache.basis_index_price_x96;
+
+    Ok(PriceStateMove {
+        trade_price_x96,
+        vertices_crossed,
+        liquidation_buffer_used: total_buffer_used,
+    })
 }
 
-// Additional helper functions like `_update_price_state` would be defined here
+/// Rebuilds the vertices after a change of the LP liquidity or of the price config. The vertices up to
+/// the current one are deferred to `pending_vertex_index`, as moving them would reprice the net
+/// position already sitting on them.
+pub fn change_price_vertices(price_state: &mut PriceState, price_config: &MarketPriceConfig, market: Pubkey, liquidity: u128, index_price_x96: u128) {
+    let current_vertex_index = price_state.current_vertex_index;
+    price_state.pending_vertex_index = current_vertex_index;
+    change_price_vertex(price_state, price_config, market, liquidity, index_price_x96, current_vertex_index, LATEST_VERTEX);
+}
+
+/// Rebuilds the vertices in `(start_exclusive, end_inclusive]` from the vertex configs, keeping the
+/// sizes and premium rates increasing along the curve.
+pub fn change_price_vertex(price_state: &mut PriceState, price_config: &MarketPriceConfig, market: Pubkey, liquidity: u128, index_price_x96: u128, start_exclusive: u8, end_inclusive: u8) {
+    _change_price_vertex(price_state, price_config, market, liquidity, index_price_x96, start_exclusive, end_inclusive, true);
+}
 
+#[allow(clippy::too_many_arguments)]
+fn _change_price_vertex(price_state: &mut PriceState, price_config: &MarketPriceConfig, market: Pubkey, liquidity: u128, index_price_x96: u128, start_exclusive: u8, end_inclusive: u8, emit_events: bool) {
+    let liquidity = std::cmp::min(liquidity, price_config.max_price_impact_liquidity);
+    let mut end_inclusive = end_inclusive;
+    let mut index = start_exclusive + 1;
+    while index <= end_inclusive {
+        let (mut size_after, mut premium_rate_after_x96) = calculate_price_vertex(&price_config.vertices[index as usize], liquidity, index_price_x96);
+        if index > 1 {
+            let previous = &price_state.price_vertices[(index - 1) as usize];
+            if previous.size >= size_after || previous.premium_rate_x96 >= premium_rate_after_x96 {
+                (size_after, premium_rate_after_x96) = (previous.size, previous.premium_rate_x96);
+            }
+        }
+        price_state.price_vertices[index as usize] = PriceVertex {
+            size: size_after,
+            premium_rate_x96: premium_rate_after_x96,
+        };
+        if emit_events {
+            emit!(PriceVertexChanged{ market, index, size_after, premium_rate_after_x96 });
+        }
+
+        // the vertices past the range are rebuilt too once they no longer lie above it
+        if index == end_inclusive && end_inclusive < LATEST_VERTEX {
+            let next = &price_state.price_vertices[(index + 1) as usize];
+            if size_after >= next.size || premium_rate_after_x96 >= next.premium_rate_x96 {
+                end_inclusive = LATEST_VERTEX;
+            }
+        }
+        index += 1;
+    }
+}
+
+// The size at which the LP net position reaches the vertex's balance rate of `liquidity`, and its premium rate
+pub fn calculate_price_vertex(vertex_config: &VertexConfig, liquidity: u128, index_price_x96: u128) -> (u128, u128) {
+    let balance_rate_x96 = Q96 * vertex_config.balance_rate as u128 / BASIS_POINTS_DIVISOR;
+    let size = mul_div(balance_rate_x96, liquidity, index_price_x96);
+    let premium_rate_x96 = Q96 * vertex_config.premium_rate as u128 / BASIS_POINTS_DIVISOR;
+    (size, premium_rate_x96)
+}
 
 pub fn _update_price_state(
-    global_position_cache: &GlobalLiquidityPosition, 
+    global_position_cache: &GlobalLiquidityPosition,
     price_state: &mut PriceState,
-    price_state_cache: &mut PriceStateCache, 
-    parameter: &UpdatePriceStateParameter, 
+    price_state_cache: &mut PriceStateCache,
+    parameter: &UpdatePriceStateParameter,
     improve_balance: bool,
-) -> (i128, u128, u128) {
-    // Initialized with a default vertex
+) -> Result<(U256, u128, u128, u8)> {
     let default_vertex = PriceVertex{
         size: 0,
         premium_rate_x96 : 0
@@ -98,114 +320,89 @@ pub fn _update_price_state(
             size: global_position_cache.net_size,
             premium_rate_x96: price_state_cache.premium_rate_x96,
         },
-        to: default_vertex.clone(), // Assuming a default implementation or placeholder
+        to: default_vertex,
     };
 
-    let mut trade_price_x96_times_size_total: i128 = 0;
+    let mut trade_price_x96_times_size_total = U256::default();
     let mut total_buffer_used: u128 = 0;
+    let mut vertices_crossed: u8 = 0;
 
-    // Logic for adjusting the price state
-    // This is a simplified placeholder logic. You will replace this with actual logic.
     if !step.improve_balance {
-        // Simulate some adjustments based on your logic
-        // For demonstration, let's iterate through some vertices as an example
-        for i in price_state_cache.current_vertex_index..price_state.price_vertices.len() as u128 {
-            let vertex = &price_state.price_vertices[(i-1) as usize];
-            step.from = vertex.clone();
-            step.to = (&price_state.price_vertices[i as usize]).clone();
-
-            
-            // Placeholder for simulate_move call - replace with actual logic
-            // let (trade_price_x96, size_used, _, premium_rate_after_x96) = simulate_move(&step);
-            let (trade_price_x96, size_used, _, premium_rate_after_x96) = simulate_move(&mut step);
-            if size_used < step.size_left && !(parameter.liquidation && price_state_cache.liquidation_vertex_index == i as u8) {
-                price_state_cache.current_vertex_index = i+1;
-                step.current = step.to.clone();
-            } 
-            // Placeholder adjustments
-            trade_price_x96_times_size_total += trade_price_x96*(size_used as i128 );
-            total_buffer_used += size_used;
-            
-            
-            step.size_left = step.size_left.saturating_sub(size_used);
+        // the balance rate got worse, move up the vertices until the liquidation vertex
+        if price_state_cache.current_vertex_index == 0 {
+            price_state_cache.current_vertex_index = 1;
         }
-
-        if(step.size_left > 0){
-            if(!parameter.liquidation) {
-                return (-1 , 0 ,0); // throw error at this condition 
+        let end = price_state_cache.liquidation_vertex_index + 1;
+        let mut i = price_state_cache.current_vertex_index;
+        while i < end && step.size_left > 0 {
+            step.from = price_state.price_vertices[(i - 1) as usize].clone();
+            step.to = price_state.price_vertices[i as usize].clone();
+            let (trade_price_x96, size_used, _, premium_rate_after_x96) = simulate_move(&step);
+
+            // crossing the liquidation vertex is left to the liquidation buffer below
+            if size_used < step.size_left && !(parameter.liquidation && i == price_state_cache.liquidation_vertex_index) {
+                price_state_cache.current_vertex_index = i + 1;
+                step.current = step.to.clone();
+                vertices_crossed += 1;
             }
+            price_state_cache.premium_rate_x96 = premium_rate_after_x96;
 
-            // Assuming `priceVertices` is accessible and `liquidationVertexIndex` is within bounds
-    let vertex = price_state.price_vertices[price_state_cache.liquidation_vertex_index as usize].clone(); // Clone if necessary
+            step.size_left -= size_used;
+            trade_price_x96_times_size_total += U256::mul(trade_price_x96, size_used);
+            i += 1;
+        }
 
-    step.to = vertex.clone();
-    step.from = vertex.clone();
-    step.current = vertex; 
-    let ( trade_price , _ , _ , _) = simulate_move(&mut step);
-    total_buffer_used+=step.size_left;
-    let liquidation_vertex_index = price_state_cache.liquidation_vertex_index;
-    let liquidation_buffer_net_size_after = price_state.liquidation_buffer_net_sizes.get(liquidation_vertex_index as usize).unwrap() + step.size_left;
-    price_state.liquidation_buffer_net_sizes[liquidation_vertex_index as usize] = liquidation_buffer_net_size_after;
+        if step.size_left > 0 {
+            require!(parameter.liquidation , Errors::MaxPremiumRateExceeded);
 
-        }
-    }
-// Else part for when the balance rate got better
-else {
-    // Logic when balance rate got better
-    for (index, vertex) in price_state.price_vertices.iter().enumerate().rev() {
-        let i = index ; // assuming the use of u8 for indexing aligns with your data sizes
-        let mut buffer_size_after = *price_state.liquidation_buffer_net_sizes.get(i).unwrap();
-        let mut size_used = 0;
-        if(buffer_size_after > 0){
-            let vertex = price_state.price_vertices[index as usize].clone();
-            step.from = vertex.clone();
-            step.to = vertex.clone();
-            size_used = std::cmp::min(buffer_size_after, step.size_left);
-            buffer_size_after-=size_used;
-            price_state.liquidation_buffer_net_sizes[i] = buffer_size_after;
-            total_buffer_used+=size_used;
-            step.size_left-=size_used;
-            let (trade_price_x96, size_used, _, premium_rate_after_x96) = simulate_move(&mut step);
-            trade_price_x96_times_size_total+=trade_price_x96*(size_used as i128);
+            // the rest sits in the liquidation buffer at the liquidation vertex's premium rate
+            let liquidation_vertex_index = price_state_cache.liquidation_vertex_index as usize;
+            let liquidation_vertex = price_state.price_vertices[liquidation_vertex_index].clone();
+            price_state.liquidation_buffer_net_sizes[liquidation_vertex_index] += step.size_left;
+            total_buffer_used += step.size_left;
 
+            let trade_price_x96 = calculate_market_price_x96(!parameter.side, parameter.side, parameter.index_price_x96, step.basis_index_price_x96, liquidation_vertex.premium_rate_x96);
+            trade_price_x96_times_size_total += U256::mul(trade_price_x96, step.size_left);
+            step.size_left = 0;
+            price_state_cache.premium_rate_x96 = liquidation_vertex.premium_rate_x96;
         }
-        step.from = vertex.clone();
-        step.to = if i > 0 {
-            price_state.price_vertices[i as usize - 1].clone()
-        } else {
-            vertex.clone() // handle the edge case for i == 0
-        };
-
-        
+    } else {
+        // the balance rate got better, empty each vertex's liquidation buffer before moving down to the one below
+        let mut i = price_state_cache.current_vertex_index as usize;
+        while step.size_left > 0 {
+            let buffer_size = price_state.liquidation_buffer_net_sizes[i];
+            if buffer_size > 0 {
+                let size_used = std::cmp::min(buffer_size, step.size_left);
+                let trade_price_x96 = calculate_market_price_x96(global_position_cache.side, parameter.side, parameter.index_price_x96, step.basis_index_price_x96, price_state.price_vertices[i].premium_rate_x96);
+                price_state.liquidation_buffer_net_sizes[i] = buffer_size - size_used;
+                total_buffer_used += size_used;
+                step.size_left -= size_used;
+                trade_price_x96_times_size_total += U256::mul(trade_price_x96, size_used);
+            }
 
-        if size_used < step.size_left {
-            step.from = price_state.price_vertices.get(i as usize).unwrap().clone();
-            step.to = price_state.price_vertices.get(i-1 as usize).unwrap().clone();
-            let (trade_price_x96, size_used, reached , premium_rate_after_x96) = simulate_move(&mut step);
-            if reached {
-                price_state_cache.current_vertex_index = (i-1) as u128;
-                step.current = step.to;
+            if i == 0 {
+                break;
             }
-            step.size_left-=size_used;
-            trade_price_x96_times_size_total+=trade_price_x96*(size_used as i128 );
-            price_state_cache.premium_rate_x96 = premium_rate_after_x96;
 
+            if step.size_left > 0 {
+                step.from = price_state.price_vertices[i].clone();
+                step.to = price_state.price_vertices[i - 1].clone();
+                let (trade_price_x96, size_used, reached, premium_rate_after_x96) = simulate_move(&step);
+                if reached {
+                    price_state_cache.current_vertex_index = (i - 1) as u8;
+                    step.current = step.to.clone();
+                    vertices_crossed += 1;
+                }
+                price_state_cache.premium_rate_x96 = premium_rate_after_x96;
+
+                step.size_left -= size_used;
+                trade_price_x96_times_size_total += U256::mul(trade_price_x96, size_used);
+            }
+            i -= 1;
         }
-
-        // Update calculations
-        // trade_price_x96_times_size_total += trade_price_x96 * size_used as i128;
-        // total_buffer_used += size_used;
-        // step.size_left = step.size_left.saturating_sub(size_used);
     }
 
-
-}
-// Assuming the rest of your function logic follows here
-
-
-    // Return calculated values
-    // These are placeholders; replace them with actual calculated values
-    (trade_price_x96_times_size_total, step.size_left, total_buffer_used)
+    Ok((trade_price_x96_times_size_total, step.size_left, total_buffer_used, vertices_crossed))
 }
 
 fn calculate_reached_and_size_used(step: &SimulateMoveStep) -> (bool, u128) {
@@ -220,98 +417,51 @@ fn calculate_reached_and_size_used(step: &SimulateMoveStep) -> (bool, u128) {
     (reached, size_used)
 }
 
-
-pub fn simulate_move(step: &mut SimulateMoveStep) -> (i128, u128, bool, u128) {
+/// Moves from `step.current` towards `step.to` and returns the trade price of the move, the size
+/// used, whether `step.to` was reached and the premium rate after the move.
+pub fn simulate_move(step: &SimulateMoveStep) -> (u128, u128, bool, u128) {
     let (reached, size_used) = calculate_reached_and_size_used(step);
     let premium_rate_after_x96 = calculate_premium_price_after(step, reached, size_used);
     let premium_rate_before_x96 = step.current.premium_rate_x96;
 
-    let (price_delta_x96_down, price_delta_x96_up) = (100, 100 );   //add the multdiv library    mul_div_2(step.basis_index_price_x96, premium_rate_before_x96 + premium_rate_after_x96, Q96 << 1);
+    // the move trades at the average of the premium rates before and after it
+    let (price_delta_x96_down, price_delta_x96_up) = mul_div2(step.basis_index_price_x96, premium_rate_before_x96 + premium_rate_after_x96, Q96 << 1);
 
-    let trade_price_x96 = if step.side { 
+    let trade_price_x96 = if step.side {
         if step.improve_balance {
-            (step.index_price_x96 as i128 - price_delta_x96_down as i128).max(0)
+            step.index_price_x96.saturating_sub(price_delta_x96_down)
         } else {
-            (step.index_price_x96 + price_delta_x96_up) as i128
+            step.index_price_x96 + price_delta_x96_up
         }
-    } else { 
+    } else {
         if step.improve_balance {
-            (step.index_price_x96 + price_delta_x96_down) as i128
+            step.index_price_x96 + price_delta_x96_down
         } else {
-            (step.index_price_x96 as i128 - price_delta_x96_up as i128).max(0)
+            step.index_price_x96.saturating_sub(price_delta_x96_up)
         }
     };
 
     (trade_price_x96, size_used, reached, premium_rate_after_x96)
 }
 
-
-pub fn calculate_ax248_and_bx96(side : bool , from :  PriceVertex , to : PriceVertex) -> (u128 , i128){
-    let mut to_new = to;
-    let mut from_new = from;
-    if from_new.size > to_new.size {
-        let temp = from_new;
-        from_new = to_new;
-        to_new = temp;
-    }
-
-    if to_new.premium_rate_x96 >= from_new.premium_rate_x96 {
-        return (0 ,0);
-    }
-
-    let size_delta = to_new.size - from_new.size;
-    let ax348 = (to_new.premium_rate_x96 - from_new.premium_rate_x96) * 100 /size_delta;
-    let mut bx96: i128 = 0;
-    let numerator_part_1_x96 = (from_new.premium_rate_x96 as u128) *to_new.size; 
-    let numerator_part_2_x96 = (to_new.premium_rate_x96 as u128) *from_new.size;
-    if side {
-        if numerator_part_1_x96 >= numerator_part_2_x96 {
-            bx96 = ((numerator_part_1_x96 - numerator_part_2_x96) / size_delta) as i128 ;
-        }
-
-        else {
-            bx96 = ((numerator_part_2_x96 - numerator_part_1_x96) / size_delta) as i128;
-        }
-
-    }
-
-    else {
-        if numerator_part_2_x96 >= numerator_part_1_x96 {
-            bx96 = ((numerator_part_2_x96 - numerator_part_1_x96) / size_delta) as i128;
-        }
-
-        else {
-            bx96 = -((numerator_part_2_x96 as i128 - numerator_part_1_x96 as i128) / size_delta as i128);
-        }
-
-    }
-
-    return (ax348 , bx96);
-
-
-    (100 , 100)
-}
-
-pub fn calculate_market_price_x96(global_side : bool , side : bool , index_price : u128 , basis_index_price : u128 ,premium_rate : u128) -> (u128 ) {
-    let price_delta_down = 100 ; // add multdiv here after library creation 
-    let  price_delta_up = 100;
-    let mut market_price =10;
+/// The price a `side` trade gets at `premium_rate`, given the side of the LP net position.
+pub fn calculate_market_price_x96(global_side : bool , side : bool , index_price : u128 , basis_index_price : u128 ,premium_rate : u128) -> u128 {
+    let (price_delta_down, price_delta_up) = mul_div2(basis_index_price, premium_rate, Q96);
     if global_side {
-        market_price = if side {
-            if index_price > price_delta_down { index_price - price_delta_down } else { 0 }
+        if side {
+            index_price.saturating_sub(price_delta_down)
         } else {
-            if index_price > price_delta_up { index_price - price_delta_up } else { 0 }
-        };
+            index_price.saturating_sub(price_delta_up)
+        }
     } else {
-        market_price = if side {
+        if side {
             index_price + price_delta_up
         } else {
             index_price + price_delta_down
-        };
+        }
     }
-
-    market_price
 }
+
 #[error_code]
 pub enum Errors {
     #[msg("Unauthorized access")]
@@ -324,8 +474,66 @@ pub enum Errors {
     InsufficientExecutionFee,
     #[msg("Cannot cancel")]
     CannotCancel,
+    #[msg("Max premium rate exceeded")]
+    MaxPremiumRateExceeded,
+}
+
+
+// What a trade did to the price state
+struct PriceStateMove {
+    trade_price_x96: u128,
+    vertices_crossed: u8,
+    liquidation_buffer_used: u128,
+}
+
+/// Outcome of a simulated trade
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TradeQuote {
+    /// Size-weighted price of the trade, as a Q64.96
+    pub trade_price_x96: u128,
+    /// Distance of the trade price from the index price in basis points, positive when against the trader
+    pub price_impact_rate: i32,
+    /// The premium rate after the trade, as a Q32.96
+    pub premium_rate_after_x96: u128,
+    /// Number of price vertices the premium rate moves past
+    pub vertices_crossed: u8,
+    pub trading_fee: u128,
+    /// Whether the trade would consume part of a liquidation buffer
+    pub liquidation_buffer_used: bool,
 }
 
+/// Current market prices of a market
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct MarketPrice {
+    pub long_market_price_x96: u128,
+    pub short_market_price_x96: u128,
+    pub basis_index_price_x96: u128,
+    /// The premium rate, as a Q32.96
+    pub premium_rate_x96: u128,
+}
+
+#[event]
+pub struct PriceStateChanged {
+    pub market: Pubkey,
+    pub side: bool,
+    pub size_delta: u128,
+    pub trade_price_x96: u128,
+    pub premium_rate_after_x96: u128,
+    pub current_vertex_index: u8,
+    pub basis_index_price_x96: u128,
+    /// Side of the LP net position after the trade
+    pub global_side: bool,
+    pub net_size_after: u128,
+    pub liquidation_buffer_net_size_after: u128,
+}
+
+#[event]
+pub struct PriceVertexChanged {
+    pub market: Pubkey,
+    pub index: u8,
+    pub size_after: u128,
+    pub premium_rate_after_x96: u128,
+}
 
 #[derive(Accounts)]
 pub struct UpdatePriceState<> {
@@ -340,7 +548,7 @@ pub struct UpdatePriceStateParameter {
     pub market: Pubkey,
     pub side: bool, // Side might be an enum you need to define based on your application logic
     pub size_delta: u128,
-    pub index_price_x96: u64,
+    pub index_price_x96: u128,
     pub liquidation_vertex_index: u8,
     pub liquidation: bool,
 }
@@ -350,8 +558,8 @@ pub struct SimulateMoveStep {
     // Similarly translate fields from Solidity to Rust
     pub side: bool,
     pub size_left: u128,
-    pub index_price_x96: u64,
-    pub basis_index_price_x96: u64,
+    pub index_price_x96: u128,
+    pub basis_index_price_x96: u128,
     pub improve_balance: bool,
     // Convert addresses to Pubkey or other suitable types
     pub from: PriceVertex,
@@ -364,8 +572,8 @@ pub struct PriceStateCache {
     pub premium_rate_x96: u128,
     pub pending_vertex_index: u8,
     pub liquidation_vertex_index: u8,
-    pub current_vertex_index: u128,
-    pub basis_index_price_x96: u64,
+    pub current_vertex_index: u8,
+    pub basis_index_price_x96: u128,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
@@ -374,6 +582,8 @@ pub struct GlobalLiquidityPosition {
     pub net_size: u128,
     /// The size of the net position held by all LPs in the liquidation buffer
     pub liquidation_buffer_net_size: u128,
+    /// The max size of the net position, derived from the liquidity
+    pub max_size: u128,
     /// The Previous Settlement Point Price, as a Q64.96
     pub previous_sp_price_x96: u128, // Adjusted from uint160 for simplicity
     /// The side of the position (Long or Short)
@@ -404,8 +614,8 @@ pub struct PriceVertex {
 pub struct PriceState {
     pub premium_rate_x96: u128,
     pub pending_vertex_index: u8,
-    pub current_vertex_index: u128,
-    pub basis_index_price_x96: u128, // Adjusted for simplicity
+    pub current_vertex_index: u8,
+    pub basis_index_price_x96: u128,
     pub price_vertices: [PriceVertex; 10],
     pub liquidation_buffer_net_sizes: [u128; 10],
 }
@@ -460,15 +670,310 @@ pub struct MarketFeeRateConfig {
     pub referral_discount_rate: u32,
 }
 
-#[account]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct VertexConfig {
     pub balance_rate: u32,
     pub premium_rate: u32,
 }
 
-#[account]
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
 pub struct MarketPriceConfig {
     pub max_price_impact_liquidity: u128,
     pub liquidation_vertex_index: u8,
     pub vertices: [VertexConfig; 10],
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // premium rates are multiples of 1/1024 and the index price is 1024, so a premium rate of
+    // `k * R` moves the price by exactly `k * Q96`
+    const R: u128 = Q96 >> 10;
+    const INDEX_PRICE_X96: u128 = 1024 * Q96;
+    const LIQUIDATION_VERTEX_INDEX: u8 = 7;
+
+    fn price_state() -> PriceState {
+        let vertices: [(u128, u128); 10] = [(0, 0), (10, 1), (20, 2), (40, 4), (60, 6), (80, 8), (100, 10), (150, 20), (200, 40), (300, 80)];
+        PriceState {
+            premium_rate_x96: 0,
+            pending_vertex_index: 0,
+            current_vertex_index: 0,
+            basis_index_price_x96: 0,
+            price_vertices: vertices.map(|(size, rate)| PriceVertex { size, premium_rate_x96: rate * R }),
+            liquidation_buffer_net_sizes: [0; 10],
+        }
+    }
+
+    // balance rates are multiples of 1/16, so with this liquidity a rate of `m * 625` sits at a size of `10 * m`
+    const LIQUIDITY: u128 = 1024 * 16 * 10;
+
+    fn price_config() -> MarketPriceConfig {
+        let balance_rates: [u32; 10] = [0, 1, 2, 4, 6, 8, 10, 15, 20, 30].map(|m| m * 625);
+        let premium_rates: [u32; 10] = [0, 10, 20, 40, 60, 80, 100, 200, 400, 800];
+        MarketPriceConfig {
+            max_price_impact_liquidity: u128::MAX,
+            liquidation_vertex_index: LIQUIDATION_VERTEX_INDEX,
+            vertices: std::array::from_fn(|i| VertexConfig { balance_rate: balance_rates[i], premium_rate: premium_rates[i] }),
+        }
+    }
+
+    fn global_position() -> GlobalLiquidityPosition {
+        GlobalLiquidityPosition {
+            net_size: 0,
+            liquidation_buffer_net_size: 0,
+            max_size: 0,
+            previous_sp_price_x96: 0,
+            side: false,
+            liquidity: LIQUIDITY,
+            unrealized_pnl_growth_x64: 0,
+        }
+    }
+
+    fn parameter(side: bool, size_delta: u128, liquidation: bool) -> UpdatePriceStateParameter {
+        UpdatePriceStateParameter {
+            market: Pubkey::default(),
+            side,
+            size_delta,
+            index_price_x96: INDEX_PRICE_X96,
+            liquidation_vertex_index: LIQUIDATION_VERTEX_INDEX,
+            liquidation,
+        }
+    }
+
+    fn trade(global_position: &mut GlobalLiquidityPosition, price_state: &mut PriceState, side: bool, size_delta: u128) -> u128 {
+        update_price_state(global_position, price_state, &price_config(), &parameter(side, size_delta, false)).unwrap()
+    }
+
+    #[test]
+    fn long_worsens_balance() {
+        let (mut position, mut state) = (global_position(), price_state());
+        // 10 up to the first vertex at +0.5, then 5 half way to the second at +1.25
+        let trade_price_x96 = trade(&mut position, &mut state, true, 15);
+        assert_eq!(trade_price_x96, INDEX_PRICE_X96 + Q96 * 3 / 4);
+        assert_eq!((position.side, position.net_size), (false, 15));
+        assert_eq!(state.premium_rate_x96, R * 3 / 2);
+        assert_eq!(state.current_vertex_index, 2);
+        assert_eq!(state.basis_index_price_x96, INDEX_PRICE_X96);
+    }
+
+    #[test]
+    fn short_worsens_balance() {
+        let (mut position, mut state) = (global_position(), price_state());
+        let trade_price_x96 = trade(&mut position, &mut state, false, 15);
+        assert_eq!(trade_price_x96, INDEX_PRICE_X96 - Q96 * 3 / 4);
+        assert_eq!((position.side, position.net_size), (true, 15));
+        assert_eq!(state.premium_rate_x96, R * 3 / 2);
+        assert_eq!(state.current_vertex_index, 2);
+    }
+
+    #[test]
+    fn short_improves_balance() {
+        let (mut position, mut state) = (global_position(), price_state());
+        trade(&mut position, &mut state, true, 15);
+        // 5 back to the first vertex at +1.25, then 5 half way to balance at +0.75
+        let trade_price_x96 = trade(&mut position, &mut state, false, 10);
+        assert_eq!(trade_price_x96, INDEX_PRICE_X96 + Q96);
+        assert_eq!((position.side, position.net_size), (false, 5));
+        assert_eq!(state.premium_rate_x96, R / 2);
+        assert_eq!(state.current_vertex_index, 1);
+    }
+
+    #[test]
+    fn long_improves_balance() {
+        let (mut position, mut state) = (global_position(), price_state());
+        trade(&mut position, &mut state, false, 15);
+        let trade_price_x96 = trade(&mut position, &mut state, true, 10);
+        assert_eq!(trade_price_x96, INDEX_PRICE_X96 - Q96);
+        assert_eq!((position.side, position.net_size), (true, 5));
+        assert_eq!(state.premium_rate_x96, R / 2);
+        assert_eq!(state.current_vertex_index, 1);
+    }
+
+    #[test]
+    fn crossing_balance_flips_the_side() {
+        let (mut position, mut state) = (global_position(), price_state());
+        trade(&mut position, &mut state, true, 5);
+        // 5 back to balance at +0.25, then 5 on the other side at -0.25
+        let trade_price_x96 = trade(&mut position, &mut state, false, 10);
+        assert_eq!(trade_price_x96, INDEX_PRICE_X96);
+        assert_eq!((position.side, position.net_size), (true, 5));
+        assert_eq!(state.premium_rate_x96, R / 2);
+        assert_eq!(state.current_vertex_index, 1);
+    }
+
+    #[test]
+    fn only_liquidations_use_the_liquidation_buffer() {
+        let (mut position, mut state) = (global_position(), price_state());
+        let result = update_price_state(&mut position, &mut state, &price_config(), &parameter(true, 160, false));
+        assert_eq!(result.unwrap_err(), error!(Errors::MaxPremiumRateExceeded));
+
+        // 150 along the vertices, then 10 in the buffer at the liquidation vertex's +20
+        let trade_price_x96 = update_price_state(&mut position, &mut state, &price_config(), &parameter(true, 160, true)).unwrap();
+        assert_eq!(trade_price_x96, INDEX_PRICE_X96 + Q96 * 145 / 16);
+        assert_eq!((position.side, position.net_size, position.liquidation_buffer_net_size), (false, 150, 10));
+        assert_eq!(state.liquidation_buffer_net_sizes[LIQUIDATION_VERTEX_INDEX as usize], 10);
+        assert_eq!(state.premium_rate_x96, 20 * R);
+        assert_eq!(state.current_vertex_index, LIQUIDATION_VERTEX_INDEX);
+
+        // the buffer empties first at +20, then 10 down towards the vertex below at +19
+        let trade_price_x96 = trade(&mut position, &mut state, false, 20);
+        assert_eq!(trade_price_x96, INDEX_PRICE_X96 + Q96 * 39 / 2);
+        assert_eq!((position.net_size, position.liquidation_buffer_net_size), (140, 0));
+        assert_eq!(state.liquidation_buffer_net_sizes[LIQUIDATION_VERTEX_INDEX as usize], 0);
+        assert_eq!(state.premium_rate_x96, 18 * R);
+        assert_eq!(state.current_vertex_index, LIQUIDATION_VERTEX_INDEX);
+    }
+
+    fn vertex_sizes(state: &PriceState) -> [u128; 10] {
+        state.price_vertices.clone().map(|vertex| vertex.size)
+    }
+
+    #[test]
+    fn vertices_follow_the_liquidity() {
+        let mut state = price_state();
+        state.price_vertices = std::array::from_fn(|_| PriceVertex { size: 0, premium_rate_x96: 0 });
+        change_price_vertices(&mut state, &price_config(), Pubkey::default(), LIQUIDITY, INDEX_PRICE_X96);
+        assert_eq!(vertex_sizes(&state), [0, 10, 20, 40, 60, 80, 100, 150, 200, 300]);
+        assert_eq!(state.price_vertices[3].premium_rate_x96, Q96 * 40 / 10_000);
+
+        // the max price impact liquidity caps the liquidity the vertices are built from
+        let mut price_config = price_config();
+        price_config.max_price_impact_liquidity = LIQUIDITY / 2;
+        change_price_vertices(&mut state, &price_config, Pubkey::default(), LIQUIDITY, INDEX_PRICE_X96);
+        assert_eq!(vertex_sizes(&state), [0, 5, 10, 20, 30, 40, 50, 75, 100, 150]);
+    }
+
+    #[test]
+    fn vertices_behind_the_market_wait_until_it_moves_back() {
+        let (mut position, mut state) = (global_position(), price_state());
+        trade(&mut position, &mut state, true, 15);
+        assert_eq!(state.current_vertex_index, 2);
+
+        position.liquidity = 2 * LIQUIDITY;
+        change_price_vertices(&mut state, &price_config(), Pubkey::default(), position.liquidity, INDEX_PRICE_X96);
+        assert_eq!(state.pending_vertex_index, 2);
+        assert_eq!(vertex_sizes(&state), [0, 10, 20, 80, 120, 160, 200, 300, 400, 600]);
+
+        trade(&mut position, &mut state, false, 10);
+        assert_eq!((state.current_vertex_index, state.pending_vertex_index), (1, 1));
+        assert_eq!(vertex_sizes(&state), [0, 10, 40, 80, 120, 160, 200, 300, 400, 600]);
+    }
+
+    #[test]
+    fn vertices_never_fall_below_the_deferred_ones() {
+        let (mut position, mut state) = (global_position(), price_state());
+        trade(&mut position, &mut state, true, 15);
+
+        // vertices 1 and 2 stay where they are, so the rebuilt ones cannot go below vertex 2
+        change_price_vertices(&mut state, &price_config(), Pubkey::default(), LIQUIDITY / 4, INDEX_PRICE_X96);
+        assert_eq!(vertex_sizes(&state), [0, 10, 20, 20, 20, 20, 25, 37, 50, 75]);
+        assert_eq!(state.price_vertices[5].premium_rate_x96, state.price_vertices[2].premium_rate_x96);
+    }
+
+    #[test]
+    fn quote_matches_the_trade_without_touching_the_state() {
+        let (mut position, mut state) = (global_position(), price_state());
+        let quote = quote_trade(&state, &position, &price_config(), 10, true, 15, INDEX_PRICE_X96).unwrap();
+        assert_eq!(state.premium_rate_x96, 0);
+        assert_eq!(position.net_size, 0);
+
+        let trade_price_x96 = trade(&mut position, &mut state, true, 15);
+        assert_eq!(quote, TradeQuote {
+            trade_price_x96,
+            // 0.75 over 1024
+            price_impact_rate: 7,
+            premium_rate_after_x96: state.premium_rate_x96,
+            vertices_crossed: 1,
+            // 10 basis points of 15 * 1024.75, rounded up
+            trading_fee: 16,
+            liquidation_buffer_used: false,
+        });
+
+        // an improving trade gets a better price than the index price
+        let quote = quote_trade(&state, &position, &price_config(), 10, false, 10, INDEX_PRICE_X96).unwrap();
+        assert_eq!(quote.trade_price_x96, INDEX_PRICE_X96 + Q96);
+        assert_eq!(quote.price_impact_rate, -9);
+        assert_eq!(quote.vertices_crossed, 1);
+    }
+
+    #[test]
+    fn quote_handles_wide_index_prices() {
+        // the price delta times the basis points divisor is beyond i128 at this index price
+        let index_price_x96 = INDEX_PRICE_X96 << 20;
+        let (position, state) = (global_position(), price_state());
+        let quote = quote_trade(&state, &position, &price_config(), 10, true, 15, index_price_x96).unwrap();
+        assert_eq!(quote.trade_price_x96, index_price_x96 + (Q96 << 20) * 3 / 4);
+        assert_eq!(quote.price_impact_rate, 7);
+    }
+
+    #[test]
+    fn quote_reports_the_liquidation_buffer() {
+        let (mut position, mut state) = (global_position(), price_state());
+        assert!(quote_trade(&state, &position, &price_config(), 10, true, 160, INDEX_PRICE_X96).is_err());
+
+        update_price_state(&mut position, &mut state, &price_config(), &parameter(true, 160, true)).unwrap();
+        let quote = quote_trade(&state, &position, &price_config(), 10, false, 20, INDEX_PRICE_X96).unwrap();
+        assert!(quote.liquidation_buffer_used);
+        assert_eq!(quote.vertices_crossed, 0);
+        assert_eq!(state.liquidation_buffer_net_sizes[LIQUIDATION_VERTEX_INDEX as usize], 10);
+    }
+
+    #[test]
+    fn market_price_follows_the_premium_rate() {
+        let (mut position, mut state) = (global_position(), price_state());
+        let market_price = get_market_price(&state, &position, INDEX_PRICE_X96 + Q96, INDEX_PRICE_X96);
+        assert_eq!((market_price.long_market_price_x96, market_price.short_market_price_x96), (INDEX_PRICE_X96 + Q96, INDEX_PRICE_X96));
+
+        // LPs are short after a long, so both sides trade above the index price
+        trade(&mut position, &mut state, true, 15);
+        let market_price = get_market_price(&state, &position, INDEX_PRICE_X96 + Q96, INDEX_PRICE_X96);
+        assert_eq!(market_price.long_market_price_x96, INDEX_PRICE_X96 + Q96 + Q96 * 3 / 2);
+        assert_eq!(market_price.short_market_price_x96, INDEX_PRICE_X96 + Q96 * 3 / 2);
+        assert_eq!(market_price.premium_rate_x96, R * 3 / 2);
+        assert_eq!(market_price.basis_index_price_x96, INDEX_PRICE_X96);
+    }
+
+    #[test]
+    fn settlement_marks_the_lp_net_position() {
+        let mut position = global_position();
+        position.side = true;
+        position.net_size = 8;
+        position.liquidation_buffer_net_size = 2;
+        position.liquidity = 100;
+        position.previous_sp_price_x96 = 1000 * Q96;
+
+        settle_liquidity_unrealized_pnl(&mut position, 1010 * Q96);
+        assert_eq!(position.unrealized_pnl_growth_x64, Q64 as i128);
+        assert_eq!(position.previous_sp_price_x96, 1010 * Q96);
+
+        settle_liquidity_unrealized_pnl(&mut position, 1005 * Q96);
+        assert_eq!(position.unrealized_pnl_growth_x64, (Q64 / 2) as i128);
+
+        // without a net position only the settlement point price moves
+        position.net_size = 0;
+        position.liquidation_buffer_net_size = 0;
+        settle_liquidity_unrealized_pnl(&mut position, 2000 * Q96);
+        assert_eq!(position.unrealized_pnl_growth_x64, (Q64 / 2) as i128);
+        assert_eq!(position.previous_sp_price_x96, 2000 * Q96);
+    }
+
+    #[test]
+    fn trades_settle_at_their_trade_price() {
+        let (mut position, mut state) = (global_position(), price_state());
+        let trade_price_x96 = trade(&mut position, &mut state, true, 15);
+        assert_eq!(position.previous_sp_price_x96, trade_price_x96);
+        assert_eq!(position.unrealized_pnl_growth_x64, 0);
+
+        // LPs short 15 from 1024.75 to 1025 lose 3.75, rounded up against them
+        let trade_price_x96 = trade(&mut position, &mut state, false, 10);
+        assert_eq!(position.previous_sp_price_x96, trade_price_x96);
+        assert_eq!(position.unrealized_pnl_growth_x64, -((4 * Q64).div_ceil(LIQUIDITY) as i128));
+    }
+
+    #[test]
+    fn wide_mul_div_keeps_precision() {
+        assert_eq!(math::mul_div(u128::MAX, u128::MAX, u128::MAX), u128::MAX);
+        assert_eq!(math::mul_div2(INDEX_PRICE_X96, 3, Q96 << 1), (1536, 1536));
+        assert_eq!(math::mul_div2(7, 3, 2), (10, 11));
+    }
+}
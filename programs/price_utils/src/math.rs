@@ -0,0 +1,94 @@
+/// 2^64, one in Q64
+pub const Q64: u128 = 1 << 64;
+/// 2^96, one in Q96
+pub const Q96: u128 = 1 << 96;
+
+/// 256-bit unsigned integer, wide enough for a Q96 price times a size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct U256 {
+    pub hi: u128,
+    pub lo: u128,
+}
+
+impl U256 {
+    /// Full-precision product of two u128.
+    pub fn mul(x: u128, y: u128) -> U256 {
+        const MASK: u128 = u64::MAX as u128;
+        let (x1, x0) = (x >> 64, x & MASK);
+        let (y1, y0) = (y >> 64, y & MASK);
+        let p00 = x0 * y0;
+        let p01 = x0 * y1;
+        let p10 = x1 * y0;
+        let p11 = x1 * y1;
+        let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+        U256 {
+            hi: p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64),
+            lo: (p00 & MASK) | ((mid & MASK) << 64),
+        }
+    }
+
+    /// Divides by `denominator`, returning the quotient and whether there was a remainder.
+    pub fn div_rem(self, denominator: u128) -> (u128, bool) {
+        if denominator == 0 {
+            panic!("Division by zero");
+        }
+        if self.hi >= denominator {
+            panic!("Division overflow");
+        }
+
+        let mut quotient: u128 = 0;
+        let mut remainder: u128 = self.hi;
+        for i in (0..128).rev() {
+            // remainder < denominator, so one carried-out bit means the shifted value exceeds it
+            let carry = remainder >> 127;
+            remainder = (remainder << 1) | ((self.lo >> i) & 1);
+            quotient <<= 1;
+            if carry == 1 || remainder >= denominator {
+                remainder = remainder.wrapping_sub(denominator);
+                quotient |= 1;
+            }
+        }
+        (quotient, remainder > 0)
+    }
+
+    pub fn div_down(self, denominator: u128) -> u128 {
+        self.div_rem(denominator).0
+    }
+
+    pub fn div_up(self, denominator: u128) -> u128 {
+        let (quotient, remainder) = self.div_rem(denominator);
+        if remainder { quotient + 1 } else { quotient }
+    }
+}
+
+impl std::ops::Add for U256 {
+    type Output = U256;
+
+    fn add(self, other: U256) -> U256 {
+        let (lo, carry) = self.lo.overflowing_add(other.lo);
+        let hi = self.hi.checked_add(other.hi).and_then(|hi| hi.checked_add(carry as u128)).expect("Addition overflow");
+        U256 { hi, lo }
+    }
+}
+
+impl std::ops::AddAssign for U256 {
+    fn add_assign(&mut self, other: U256) {
+        *self = *self + other;
+    }
+}
+
+/// Calculates `x * y / denominator` with rounding down, without overflowing the product.
+pub fn mul_div(x: u128, y: u128, denominator: u128) -> u128 {
+    U256::mul(x, y).div_down(denominator)
+}
+
+/// Calculates `x * y / denominator` with rounding up, without overflowing the product.
+pub fn mul_div_up(x: u128, y: u128, denominator: u128) -> u128 {
+    U256::mul(x, y).div_up(denominator)
+}
+
+/// Calculates `x * y / denominator` with both rounding down and up.
+pub fn mul_div2(x: u128, y: u128, denominator: u128) -> (u128, u128) {
+    let (result, remainder) = U256::mul(x, y).div_rem(denominator);
+    (result, if remainder { result + 1 } else { result })
+}
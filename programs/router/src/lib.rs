@@ -1,7 +1,26 @@
 use anchor_lang::{
     prelude::*
 };
-const GOVERNOR_PUBKEY: Pubkey = Pubkey::new_from_array([1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32]);
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token::{self, Mint, MintTo, SetAuthority, Token, TokenAccount, Transfer};
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use governance::{Governance, PauseRegistry, GOVERNANCE_SEED, PAUSE_REGISTRY_SEED, PAUSE_CLOSING, PAUSE_LIQUIDATION, PAUSE_LIQUIDITY, PAUSE_OPENING};
+use liquidity_position_util::program::Pool as PoolProgram;
+use liquidity_position_util::position_util::State as PoolState;
+/// Seed of the PDA users approve as delegate of the token accounts plugins may move funds from
+pub const ROUTER_AUTHORITY_SEED: &[u8] = b"router_authority";
+/// Seed of the NFT mint representing a liquidity position, followed by the pool and the position id
+pub const LIQUIDITY_POSITION_MINT_SEED: &[u8] = b"liquidity_position_mint";
+/// Seed of the approval an account grants a plugin, followed by the account and the plugin
+pub const PLUGIN_APPROVAL_SEED: &[u8] = b"plugin_approval";
+/// Permission bits governance grants a plugin at `add_plugin`
+pub const PLUGIN_PERMISSION_TRANSFER: u8 = 1 << 0;
+pub const PLUGIN_PERMISSION_TRANSFER_NFT: u8 = 1 << 1;
+pub const PLUGIN_PERMISSION_LIQUIDITY_POSITION: u8 = 1 << 2;
+pub const PLUGIN_PERMISSION_RISK_BUFFER_FUND: u8 = 1 << 3;
+pub const PLUGIN_PERMISSION_POSITION: u8 = 1 << 4;
+pub const PLUGIN_PERMISSION_LIQUIDATION: u8 = 1 << 5;
+pub const PLUGIN_PERMISSION_COLLECT: u8 = 1 << 6;
 
 
 declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
@@ -11,7 +30,7 @@ declare_id!("Fg6PaFpoGXkYsidMpWTK6W2BeZ7FEfcYkg476zPFsLnS");
 mod router {
     use super::*;
     pub fn initilize(ctx: Context<SetData>, efc: Pubkey , reward_farm : Pubkey , fee_distributor : Pubkey) -> Result<()> {
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::Unauthorized);     
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
         let state: &mut Account<'_, ContractState> =&mut ctx.accounts.state;
         require!(!state.initilized , MyError::AlreadyInitlized );
         state.efc= efc;
@@ -22,21 +41,26 @@ mod router {
     }
 
     
-    pub fn add_plugin(ctx: Context<UpdateExecutor>, new_plugin: Pubkey) -> Result<()> {
+    pub fn add_plugin(ctx: Context<UpdateExecutor>, new_plugin: Pubkey, permissions: u8) -> Result<()> {
         // Ensure the caller is the governor
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
 
-        // Add new executor to the list
+        // Add new executor to the list, re-adding a plugin replaces its permissions
         let governance_state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
-        governance_state.executors.push(new_plugin);
+        if !governance_state.executors.contains(&new_plugin) {
+            governance_state.executors.push(new_plugin);
+        }
+        governance_state.plugin_permissions.retain(|p| p.plugin != new_plugin);
+        governance_state.plugin_permissions.push(PluginPermissions{ plugin : new_plugin , permissions });
         Ok(())
     }
 
         // Function to update executor
     pub fn remove_plugin(ctx: Context<UpdateExecutor>, plugin_address: Pubkey) -> Result<()> {
-            require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+            governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
             let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
             address_list.retain(|&x| x != plugin_address);
+            ctx.accounts.state.plugin_permissions.retain(|p| p.plugin != plugin_address);
             // Logic to update executor
             Ok(())
         }
@@ -44,7 +68,7 @@ mod router {
             
     pub fn add_liquidator(ctx: Context<UpdateExecutor>, new_liquidator: Pubkey) -> Result<()> {
         // Ensure the caller is the governor
-        require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+        governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
 
         // Add new executor to the list
         let governance_state: &mut Account<'_, ContractState> = &mut ctx.accounts.state;
@@ -54,129 +78,308 @@ mod router {
 
         // Function to update executor
     pub fn remove_liquidator(ctx: Context<UpdateExecutor>, liquidator_address: Pubkey) -> Result<()> {
-            require!(ctx.accounts.authorized_account.key() == GOVERNOR_PUBKEY, MyError::CallerUnauthorized);
+            governance::require_governor(&ctx.accounts.governance, &ctx.accounts.authorized_account, ctx.remaining_accounts)?;
             let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.liquidators;
             address_list.retain(|&x| x != liquidator_address);
             // Logic to update executor
             Ok(())
         }
 
+    // Lets `plugin` act on the signer's funds and positions
+    pub fn approve_plugin(ctx: Context<ApprovePlugin>, plugin: Pubkey) -> Result<()> {
+        let approval: &mut Account<'_, PluginApproval> = &mut ctx.accounts.plugin_approval;
+        approval.account = ctx.accounts.account.key();
+        approval.plugin = plugin;
+        emit!(PluginApproved{ account : approval.account , plugin });
+        Ok(())
+    }
+
+    // Withdraws the approval the signer granted a plugin, closing the approval account
+    pub fn revoke_plugin(ctx: Context<RevokePlugin>) -> Result<()> {
+        emit!(PluginRevoked{ account : ctx.accounts.account.key() , plugin : ctx.accounts.plugin_approval.plugin });
+        Ok(())
+    }
+
     pub fn plugin_transfer(ctx: Context<PluginTransfer>, amount: u128, from: Pubkey, to: Pubkey) -> Result<()> {
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);
-        // token transfer logic
-        Ok(())
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_TRANSFER)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), from, user_pubkey)?;
+        require!(ctx.accounts.from_token.owner == from , MyError::OwnerMismatch);
+        require!(ctx.accounts.to_token.owner == to , MyError::OwnerMismatch);
+        let amount: u64 = u64::try_from(amount).map_err(|_| MyError::AmountOverflow)?;
+
+        // the router authority moves the funds as the delegate approved by `from`
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.from_token.to_account_info(),
+            to : ctx.accounts.to_token.to_account_info(),
+            authority : ctx.accounts.router_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, amount)
     }
 
-    pub fn plugin_transfer_nft(ctx: Context<PluginTransferNFT>, from: Pubkey , to:Pubkey , tokenID:u128) -> Result<()> {
-        let address_list = &mut ctx.accounts.state.executors;
+    pub fn plugin_transfer_nft(ctx: Context<PluginTransferNFT>, from: Pubkey , to:Pubkey , tokenID:u128 , pool : Pubkey) -> Result<()> {
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);
-        
-        //token transfer
-        Ok(())
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_TRANSFER_NFT)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), from, user_pubkey)?;
+        require!(ctx.accounts.position_mint.key() == liquidity_position_mint(pool, tokenID) , MyError::InvalidPositionMint);
+        _require_liquidity_position_holder(&ctx.accounts.from_token, ctx.accounts.position_mint.key(), from)?;
+        require!(ctx.accounts.to_token.mint == ctx.accounts.position_mint.key() , MyError::InvalidPositionMint);
+        require!(ctx.accounts.to_token.owner == to , MyError::OwnerMismatch);
+
+        // the router authority moves the NFT as the delegate approved by `from`
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts: Transfer<'_> = Transfer{
+            from : ctx.accounts.from_token.to_account_info(),
+            to : ctx.accounts.to_token.to_account_info(),
+            authority : ctx.accounts.router_authority.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds);
+        token::transfer(cpi_ctx, 1)
     }
 
-    pub fn plugin_open_liquidity_position(ctx: Context<LiquidityPosition>, account: Pubkey, margin:u128, liquidity:u128 , pool : Pubkey ) -> Result<u128> {
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
+    // Opens a liquidity position and mints its NFT to `account`, whose holder owns the position from then on
+    // The margin must already be in the pool vault and the pool's index token prices are read from the remaining accounts.
+    pub fn plugin_open_liquidity_position<'info>(ctx: Context<'_, '_, 'info, 'info, OpenLiquidityPosition<'info>>, account: Pubkey, margin:u128, liquidity:u128 , pool : Pubkey ) -> Result<u128> {
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized); 
-        /// external call to pool
-        Ok(100)
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_LIQUIDITY_POSITION)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), account, user_pubkey)?;
+        require!(ctx.accounts.authorized_account.key() == account , MyError::OwnerMismatch);
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+        let position_id: u128 = ctx.accounts.state.liquidity_position_count;
+        ctx.accounts.state.liquidity_position_count += 1;
+
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::IncreasePosition{
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        let pool_position_id: u64 = u64::try_from(position_id).map_err(|_| MyError::AmountOverflow)?;
+        liquidity_position_util::cpi::increase_liquidity_position(cpi_ctx, account, pool_position_id, margin, liquidity)?;
+
+        let cpi_accounts: MintTo<'_> = MintTo{
+            mint : ctx.accounts.position_mint.to_account_info(),
+            to : ctx.accounts.holder_token.to_account_info(),
+            authority : ctx.accounts.router_authority.to_account_info(),
+        };
+        token::mint_to(CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds), 1)?;
+        // dropping the mint authority caps the supply at the single position token
+        let cpi_accounts: SetAuthority<'_> = SetAuthority{
+            current_authority : ctx.accounts.router_authority.to_account_info(),
+            account_or_mint : ctx.accounts.position_mint.to_account_info(),
+        };
+        token::set_authority(CpiContext::new_with_signer(ctx.accounts.token_program.to_account_info(), cpi_accounts, signer_seeds), AuthorityType::MintTokens, None)?;
+        Ok(position_id)
     }
 
-    pub fn plugin_close_liquidity_position(ctx: Context<LiquidityPosition>,  _positionID:u128 ,  _receiver:Pubkey , pool :Pubkey ) -> Result<()> {
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
+    // Closes the liquidity position `position_id` in `pool` for its NFT holder, the pool pays out its whole margin to `receiver`.
+    // The pool's index token prices are read from the remaining accounts.
+    pub fn plugin_close_liquidity_position<'info>(ctx: Context<'_, '_, 'info, 'info, ChangeLiquidityPosition<'info>>, position_id: u128, receiver: Pubkey, pool: Pubkey) -> Result<()> {
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);   
-        // extrernal call to pool
-      Ok(())
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_LIQUIDITY_POSITION)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), ctx.accounts.authorized_account.key(), user_pubkey)?;
+        require!(ctx.accounts.position_mint.key() == liquidity_position_mint(pool, position_id) , MyError::InvalidPositionMint);
+        _require_liquidity_position_holder(&ctx.accounts.holder_token, ctx.accounts.position_mint.key(), ctx.accounts.authorized_account.key())?;
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+        let pool_position_id: u64 = u64::try_from(position_id).map_err(|_| MyError::AmountOverflow)?;
+        let index: usize = liquidity_position_util::position_util::get_liquidity_position_index(&ctx.accounts.pool_state, pool_position_id).ok_or(MyError::InvalidPositionMint)?;
+        let liquidity: u128 = ctx.accounts.pool_state.liquidity_positions[index].liquidity;
+
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), _pool_decrease_accounts(ctx.accounts), signer_seeds)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        liquidity_position_util::cpi::decrease_liquidity_position(cpi_ctx, pool_position_id, 0, liquidity, receiver)?;
+        Ok(())
     }
 
-    pub fn plugin_adjust_liquidity_position_margin(ctx: Context<LiquidityPosition>, _pool:Pubkey,
-        _positionID:u128,
-        _marginDelta:u128,
-        _receiver:Pubkey) -> Result<()> {
-            let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
+    // Adjusts the margin of the liquidity position `position_id` in `pool` for its NFT holder. Added margin must already be in the
+    // pool vault, withdrawn margin is paid to `receiver`. The pool's index token prices are read from the remaining accounts.
+    pub fn plugin_adjust_liquidity_position_margin<'info>(ctx: Context<'_, '_, 'info, 'info, ChangeLiquidityPosition<'info>>, pool: Pubkey,
+        position_id: u128,
+        margin_delta: i128,
+        receiver: Pubkey) -> Result<()> {
             let user_pubkey: Pubkey = ctx.accounts.user.key();
-            require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);
-        // return a u128 value
-      Ok(())
+            _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_LIQUIDITY_POSITION)?;
+            governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
+            _require_plugin_approved(Some(&ctx.accounts.plugin_approval), ctx.accounts.authorized_account.key(), user_pubkey)?;
+            require!(ctx.accounts.position_mint.key() == liquidity_position_mint(pool, position_id) , MyError::InvalidPositionMint);
+            _require_liquidity_position_holder(&ctx.accounts.holder_token, ctx.accounts.position_mint.key(), ctx.accounts.authorized_account.key())?;
+            require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+            let pool_position_id: u64 = u64::try_from(position_id).map_err(|_| MyError::AmountOverflow)?;
+
+            let bump: u8 = ctx.bumps.router_authority;
+            let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+            let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), _pool_decrease_accounts(ctx.accounts), signer_seeds)
+                .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+            liquidity_position_util::cpi::adjust_liquidity_position_margin(cpi_ctx, pool_position_id, margin_delta, receiver)
     }
 
     
-      // Increase the liquidity of a risk buffer fund position
+      // Increase the liquidity of a risk buffer fund position with liquidity the plugin already moved to the pool vault
       pub fn plugin_increase_risk_buffer_fund_position(
-        ctx: Context<RiskBufferFundPosition>, 
+        ctx: Context<IncreaseRiskBufferFundPosition>, 
         pool : Pubkey , 
         account: Pubkey, 
         liquidity_delta: u128
     ) -> Result<()> {
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);  
-        Ok(())
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_RISK_BUFFER_FUND)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), account, user_pubkey)?;
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::IncreaseRiskBufferFundPositionByRouter{
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds);
+        liquidity_position_util::cpi::increase_risk_buffer_fund_position_by_router(cpi_ctx, account, liquidity_delta)
     }
 
     
-    // Decrease the liquidity of a risk buffer fund position
+    // Decrease the liquidity of an unlocked risk buffer fund position, the pool pays its share of the fund to `receiver`
     pub fn plugin_decrease_risk_buffer_fund_position(
-        ctx: Context<RiskBufferFundPosition>, 
+        ctx: Context<DecreaseRiskBufferFundPosition>, 
         pool : Pubkey , 
         account: Pubkey, 
         liquidity_delta: u128, 
         receiver: Pubkey
     ) -> Result<()> {
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);
-        Ok(())
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_RISK_BUFFER_FUND)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDITY)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), account, user_pubkey)?;
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::DecreaseRiskBufferFundPositionByRouter{
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds);
+        liquidity_position_util::cpi::decrease_risk_buffer_fund_position_by_router(cpi_ctx, account, liquidity_delta, receiver)
     }
 
-    // Increase the margin/liquidity of a position
-    pub fn plugin_increase_position(
-        ctx: Context<PositionManagement>, 
+    // Opens or increases the position in `pool` with margin the plugin already moved to the pool vault, returning the trade price.
+    // The pool's index token prices are read from the remaining accounts.
+    pub fn plugin_increase_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, IncreasePosition<'info>>, 
+        pool: Pubkey, 
         account: Pubkey, 
         side: bool, 
         margin_delta: u128, 
         size_delta: u128
     ) -> Result<u128> {
-        // TODO: Implement access control, position increase logic
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);    
-        Ok(0) // Placeholder for trade price
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_POSITION)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_OPENING)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), account, user_pubkey)?;
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::IncreasePosition{
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        Ok(liquidity_position_util::cpi::increase_position(cpi_ctx, account, side, margin_delta, size_delta)?.get())
     }
 
-    // Decrease the margin/liquidity of a position
-    pub fn plugin_decrease_position(
-        ctx: Context<PositionManagement>, 
+    // Decreases the position in `pool`, the pool pays the released margin to `receiver`. Returns the trade price.
+    // The pool's index token prices are read from the remaining accounts.
+    pub fn plugin_decrease_position<'info>(
+        ctx: Context<'_, '_, 'info, 'info, DecreasePosition<'info>>, 
+        pool: Pubkey, 
         account: Pubkey, 
         side: bool, 
         margin_delta: u128, 
         size_delta: u128, 
         receiver: Pubkey
     ) -> Result<u128> {
-        // TODO: Implement access control, position decrease logic
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);    
-        Ok(0) // Placeholder for trade price
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_POSITION)?;
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_CLOSING)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), account, user_pubkey)?;
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::DecreasePosition{
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        Ok(liquidity_position_util::cpi::decrease_position(cpi_ctx, account, side, margin_delta, size_delta, receiver)?.get())
     }
 
-    // Close a position by the liquidator
-    pub fn plugin_close_position_by_liquidator(
-        ctx: Context<PositionManagement>, 
-        _pool: Pubkey, 
+    // Closes `size_delta` of the `account`'s position in `pool` for a liquidator, the pool pays the margin left to `receiver`.
+    // The pool's index token prices are read from the remaining accounts.
+    pub fn plugin_close_position_by_liquidator<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ClosePositionByLiquidator<'info>>, 
+        pool: Pubkey, 
+        account: Pubkey, 
         side: bool, 
         size_delta: u128, 
         receiver: Pubkey
     ) -> Result<()> {
-        let address_list = &mut ctx.accounts.state.executors;
+        let address_list = &ctx.accounts.state.liquidators;
         let user_pubkey = ctx.accounts.user.key();
         require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);
-        
+        require!(_has_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_LIQUIDATION) , MyError::PluginPermissionDenied);
+        governance::require_not_paused(&ctx.accounts.pause_registry, pool, PAUSE_LIQUIDATION)?;
+        require!(ctx.accounts.pool_state.key() == pool , MyError::InvalidPool);
+        // liquidations act on unsafe positions regardless of the owner's approvals
+
+        let bump: u8 = ctx.bumps.router_authority;
+        let signer_seeds: &[&[&[u8]]] = &[&[ROUTER_AUTHORITY_SEED, &[bump]]];
+        let cpi_accounts = liquidity_position_util::cpi::accounts::DecreasePosition{
+            router_authority : ctx.accounts.router_authority.to_account_info(),
+            state : ctx.accounts.pool_state.to_account_info(),
+            pool_authority : ctx.accounts.pool_authority.to_account_info(),
+            pool_vault : ctx.accounts.pool_vault.to_account_info(),
+            receiver_token : ctx.accounts.receiver_token.to_account_info(),
+            price_feed : ctx.accounts.price_feed.to_account_info(),
+            token_price : ctx.accounts.token_price.to_account_info(),
+            token_program : ctx.accounts.token_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(ctx.accounts.pool_program.to_account_info(), cpi_accounts, signer_seeds)
+            .with_remaining_accounts(ctx.remaining_accounts.to_vec());
+        liquidity_position_util::cpi::decrease_position(cpi_ctx, account, side, 0, size_delta, receiver)?;
         Ok(())
     }
 
@@ -187,21 +390,24 @@ mod router {
             referral_token: u128, 
             receiver: Pubkey
         ) -> Result<()> {
-            let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
             let user_pubkey: Pubkey = ctx.accounts.user.key();
-            require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);        
+            _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_COLLECT)?;
+            _require_plugin_approved(ctx.accounts.plugin_approval.as_ref(), ctx.accounts.authorized_account.key(), user_pubkey)?;
             Ok(())
     }
 
+    // Collects the farm reward of the liquidity position `position_id` in `pool` for its NFT holder
     pub fn plugin_collect_farm_liquidity_reward(
-        ctx: Context<PositionManagement>, 
-        pools: Vec<Pubkey>, 
-        receiver: Pubkey,
-        owner : Pubkey
+        ctx: Context<LiquidityPositionHolder>, 
+        pool: Pubkey, 
+        position_id: u128, 
+        _receiver: Pubkey
     ) -> Result<()> {
-        let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
         let user_pubkey: Pubkey = ctx.accounts.user.key();
-        require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);        
+        _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_COLLECT)?;
+        _require_plugin_approved(Some(&ctx.accounts.plugin_approval), ctx.accounts.authorized_account.key(), user_pubkey)?;
+        require!(ctx.accounts.position_mint.key() == liquidity_position_mint(pool, position_id) , MyError::InvalidPositionMint);
+        _require_liquidity_position_holder(&ctx.accounts.holder_token, ctx.accounts.position_mint.key(), ctx.accounts.authorized_account.key())?;
         Ok(())
 }
 
@@ -211,13 +417,70 @@ pub fn plugin_collect_farm_risk_buffer_reward_batch(
     receiver: Pubkey,
     owner : Pubkey
 ) -> Result<()> {
-    let address_list: &mut Vec<Pubkey> = &mut ctx.accounts.state.executors;
     let user_pubkey: Pubkey = ctx.accounts.user.key();
-    require!(address_list.contains(&user_pubkey) , MyError::CallerUnauthorized);        
+    _require_plugin_permission(&ctx.accounts.state, user_pubkey, PLUGIN_PERMISSION_COLLECT)?;
+    _require_plugin_approved(ctx.accounts.plugin_approval.as_ref(), owner, user_pubkey)?;
+    Ok(())
+}
+
+
+}
+
+/// Address of the NFT mint of the liquidity position `position_id` in `pool`.
+pub fn liquidity_position_mint(pool: Pubkey, position_id: u128) -> Pubkey {
+    Pubkey::find_program_address(&[LIQUIDITY_POSITION_MINT_SEED, pool.as_ref(), &position_id.to_le_bytes()], &crate::ID).0
+}
+
+/// Whether governance granted `plugin` every bit of `permission`.
+pub fn _has_plugin_permission(state: &ContractState, plugin: Pubkey, permission: u8) -> bool {
+    state.plugin_permissions.iter().any(|p| p.plugin == plugin && p.permissions & permission == permission)
+}
+
+/// Errors unless `plugin` is a registered executor holding `permission`.
+pub fn _require_plugin_permission(state: &ContractState, plugin: Pubkey, permission: u8) -> Result<()> {
+    require!(state.executors.contains(&plugin) , MyError::CallerUnauthorized);
+    require!(_has_plugin_permission(state, plugin, permission) , MyError::PluginPermissionDenied);
+    Ok(())
+}
+
+/// Errors unless `account` has approved `plugin` through `approval`.
+pub fn _require_plugin_approved(approval: Option<&Account<'_, PluginApproval>>, account: Pubkey, plugin: Pubkey) -> Result<()> {
+    let approval = approval.ok_or(MyError::PluginNotApproved)?;
+    require!(approval.account == account && approval.plugin == plugin , MyError::PluginNotApproved);
     Ok(())
 }
 
+/// Pool accounts of a liquidity position change that may pay margin out.
+fn _pool_decrease_accounts<'info>(accounts: &ChangeLiquidityPosition<'info>) -> liquidity_position_util::cpi::accounts::DecreasePosition<'info> {
+    liquidity_position_util::cpi::accounts::DecreasePosition{
+        router_authority : accounts.router_authority.to_account_info(),
+        state : accounts.pool_state.to_account_info(),
+        pool_authority : accounts.pool_authority.to_account_info(),
+        pool_vault : accounts.pool_vault.to_account_info(),
+        receiver_token : accounts.receiver_token.to_account_info(),
+        price_feed : accounts.price_feed.to_account_info(),
+        token_price : accounts.token_price.to_account_info(),
+        token_program : accounts.token_program.to_account_info(),
+    }
+}
 
+/// Errors unless `account` holds the NFT of `position_mint` in `holder_token`.
+pub fn _require_liquidity_position_holder(holder_token: &TokenAccount, position_mint: Pubkey, account: Pubkey) -> Result<()> {
+    require!(holder_token.mint == position_mint , MyError::InvalidPositionMint);
+    require!(holder_token.owner == account && holder_token.amount == 1 , MyError::NotPositionHolder);
+    Ok(())
+}
+
+#[account]
+pub struct PluginApproval {
+    pub account : Pubkey,
+    pub plugin : Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct PluginPermissions {
+    pub plugin : Pubkey,
+    pub permissions : u8,
 }
 
 #[account] 
@@ -229,6 +492,8 @@ pub struct ContractState {
     initilized : bool,
     executors : Vec<Pubkey>,
     liquidators : Vec<Pubkey>,         
+    liquidity_position_count : u128,
+    plugin_permissions : Vec<PluginPermissions>,
 
 }
 
@@ -240,6 +505,8 @@ pub struct UpdateExecutor<'info> {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
     pub user: Signer<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
 }
 
 #[derive(Accounts)]
@@ -250,6 +517,39 @@ pub struct PluginTransfer<'info> {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
     pub user: Signer<'info>,
+    #[account(mut)]
+    pub from_token: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub to_token: Account<'info, TokenAccount>,
+    /// CHECK: PDA only used as the delegate signing the transfer
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+}
+
+#[derive(Accounts)]
+#[instruction(plugin: Pubkey)]
+pub struct ApprovePlugin<'info> {
+    #[account(mut)]
+    pub account: Signer<'info>,
+    #[account(
+        init,
+        payer = account,
+        space = 8 + 32 + 32,
+        seeds = [PLUGIN_APPROVAL_SEED, account.key().as_ref(), plugin.as_ref()],
+        bump,
+    )]
+    pub plugin_approval: Account<'info, PluginApproval>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokePlugin<'info> {
+    #[account(mut)]
+    pub account: Signer<'info>,
+    #[account(mut, close = account, has_one = account)]
+    pub plugin_approval: Account<'info, PluginApproval>,
 }
 
 #[derive(Accounts)]
@@ -260,20 +560,118 @@ pub struct PluginTransferNFT<'info>  {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
     pub user: Signer<'info>,
+    pub position_mint: Account<'info, Mint>,
+    #[account(mut)]
+    pub from_token: Account<'info, TokenAccount>,
+    #[account(mut)]
+    pub to_token: Account<'info, TokenAccount>,
+    /// CHECK: PDA only used as the delegate signing the transfer
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub plugin_approval: Account<'info, PluginApproval>,
 }
 
 #[derive(Accounts)]
-pub struct LiquidityPosition<'info>  {
-     // Adjust space as needed
+#[instruction(account: Pubkey, margin: u128, liquidity: u128, pool: Pubkey)]
+pub struct OpenLiquidityPosition<'info>  {
     /// CHECK
     #[account(signer)]
     pub authorized_account: AccountInfo<'info>,
+    #[account(mut)]
+    pub state: Account<'info, ContractState>,
+    pub user: Signer<'info>,
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [LIQUIDITY_POSITION_MINT_SEED, pool.as_ref(), &state.liquidity_position_count.to_le_bytes()],
+        bump,
+        mint::decimals = 0,
+        mint::authority = router_authority,
+    )]
+    pub position_mint: Account<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = position_mint,
+        associated_token::authority = authorized_account,
+    )]
+    pub holder_token: Account<'info, TokenAccount>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+    /// CHECK: PDA used as the mint authority of position NFTs
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    pub pool_program: Program<'info, PoolProgram>,
+}
+
+#[derive(Accounts)]
+pub struct LiquidityPositionHolder<'info>  {
+    /// CHECK: must hold the position NFT
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
     pub user: Signer<'info>,
+    pub position_mint: Account<'info, Mint>,
+    pub holder_token: Account<'info, TokenAccount>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct ChangeLiquidityPosition<'info>  {
+    /// CHECK: must hold the position NFT
+    #[account(signer)]
+    pub authorized_account: AccountInfo<'info>,
+    pub state: Account<'info, ContractState>,
+    pub user: Signer<'info>,
+    pub position_mint: Account<'info, Mint>,
+    pub holder_token: Account<'info, TokenAccount>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: PDA the router signs pool calls with
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    #[account(mut)]
+    pub pool_state: Account<'info, PoolState>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub receiver_token: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub pool_program: Program<'info, PoolProgram>,
 }
 
 #[derive(Accounts)]
-pub struct RiskBufferFundPosition<'info>  {
+pub struct LiquidityPosition<'info>  {
      // Adjust space as needed
     /// CHECK
     #[account(signer)]
@@ -282,6 +680,55 @@ pub struct RiskBufferFundPosition<'info>  {
     pub user: Signer<'info>,
 }
 
+#[derive(Accounts)]
+pub struct IncreaseRiskBufferFundPosition<'info>  {
+    /// CHECK: owner of the position, bound by the plugin approval
+    pub authorized_account: AccountInfo<'info>,
+    pub state: Account<'info, ContractState>,
+    pub user: Signer<'info>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: PDA the router signs pool calls with
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_vault: AccountInfo<'info>,
+    pub pool_program: Program<'info, PoolProgram>,
+}
+
+#[derive(Accounts)]
+pub struct DecreaseRiskBufferFundPosition<'info>  {
+    /// CHECK: owner of the position, bound by the plugin approval
+    pub authorized_account: AccountInfo<'info>,
+    pub state: Account<'info, ContractState>,
+    pub user: Signer<'info>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: PDA the router signs pool calls with
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub receiver_token: AccountInfo<'info>,
+    pub token_program: Program<'info, Token>,
+    pub pool_program: Program<'info, PoolProgram>,
+}
+
 #[derive(Accounts)]
 pub struct PositionManagement<'info>  {
      // Adjust space as needed
@@ -290,7 +737,95 @@ pub struct PositionManagement<'info>  {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
     pub user: Signer<'info>,
+    // not needed when a liquidator closes a position
+    pub plugin_approval: Option<Account<'info, PluginApproval>>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct IncreasePosition<'info> {
+    /// CHECK: owner of the position, bound by the plugin approval
+    pub authorized_account: AccountInfo<'info>,
+    pub state: Account<'info, ContractState>,
+    pub user: Signer<'info>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: PDA the router signs pool calls with
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    pub pool_program: Program<'info, PoolProgram>,
+}
+
+#[derive(Accounts)]
+pub struct DecreasePosition<'info> {
+    /// CHECK: owner of the position, bound by the plugin approval
+    pub authorized_account: AccountInfo<'info>,
+    pub state: Account<'info, ContractState>,
+    pub user: Signer<'info>,
+    pub plugin_approval: Account<'info, PluginApproval>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: PDA the router signs pool calls with
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool against the receiver
+    #[account(mut)]
+    pub receiver_token: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    pub pool_program: Program<'info, PoolProgram>,
+    pub token_program: Program<'info, Token>,
+}
 
+#[derive(Accounts)]
+pub struct ClosePositionByLiquidator<'info> {
+    pub state: Account<'info, ContractState>,
+    pub user: Signer<'info>,
+    #[account(seeds = [PAUSE_REGISTRY_SEED], bump, seeds::program = governance::ID)]
+    pub pause_registry: Account<'info, PauseRegistry>,
+    /// CHECK: PDA the router signs pool calls with
+    #[account(seeds = [ROUTER_AUTHORITY_SEED], bump)]
+    pub router_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_state: AccountInfo<'info>,
+    /// CHECK: PDA owning the pool vault, validated by the pool
+    pub pool_authority: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    #[account(mut)]
+    pub pool_vault: AccountInfo<'info>,
+    /// CHECK: validated by the pool against the receiver
+    #[account(mut)]
+    pub receiver_token: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub price_feed: AccountInfo<'info>,
+    /// CHECK: validated by the pool
+    pub token_price: AccountInfo<'info>,
+    pub pool_program: Program<'info, PoolProgram>,
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
@@ -343,6 +878,20 @@ pub struct SetData<'info> {
     pub authorized_account: AccountInfo<'info>,
     pub state: Account<'info, ContractState>,
     pub user: Signer<'info>,
+    #[account(seeds = [GOVERNANCE_SEED], bump, seeds::program = governance::ID)]
+    pub governance: Account<'info, Governance>,
+}
+
+#[event]
+pub struct PluginApproved {
+    pub account : Pubkey,
+    pub plugin : Pubkey,
+}
+
+#[event]
+pub struct PluginRevoked {
+    pub account : Pubkey,
+    pub plugin : Pubkey,
 }
 
 #[error_code]
@@ -356,6 +905,18 @@ pub enum MyError {
     OwnerMismatch , 
     #[msg("Program already initlized")]
     AlreadyInitlized,
+    #[msg("Amount overflow")]
+    AmountOverflow,
+    #[msg("Invalid position mint")]
+    InvalidPositionMint,
+    #[msg("Not the position holder")]
+    NotPositionHolder,
+    #[msg("Plugin not approved")]
+    PluginNotApproved,
+    #[msg("Plugin lacks the permission")]
+    PluginPermissionDenied,
+    #[msg("Pool mismatch")]
+    InvalidPool,
 }
 
 
@@ -0,0 +1,116 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program, BN } from "@coral-xyz/anchor";
+import {
+  BPF_LOADER_UPGRADEABLE_PROGRAM_ID,
+  Ed25519Program,
+  Keypair,
+  PublicKey,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+} from "@solana/web3.js";
+import { assert } from "chai";
+import { Governance } from "../target/types/governance";
+import { PriceFeed } from "../target/types/price_feed";
+
+// Borsh layout of Vec<SignedPrice>, the message updaters sign
+function serializePrices(
+  prices: { token: PublicKey; minPriceX96: BN; maxPriceX96: BN; timestamp: BN }[]
+): Buffer {
+  const parts: Buffer[] = [];
+  const len = Buffer.alloc(4);
+  len.writeUInt32LE(prices.length);
+  parts.push(len);
+  for (const price of prices) {
+    parts.push(price.token.toBuffer());
+    parts.push(price.minPriceX96.toArrayLike(Buffer, "le", 16));
+    parts.push(price.maxPriceX96.toArrayLike(Buffer, "le", 16));
+    parts.push(price.timestamp.toTwos(64).toArrayLike(Buffer, "le", 8));
+  }
+  return Buffer.concat(parts);
+}
+
+describe("price-feed", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const governance = anchor.workspace.Governance as Program<Governance>;
+  const priceFeed = anchor.workspace.PriceFeed as Program<PriceFeed>;
+  const governor = provider.wallet.publicKey;
+  const updater = Keypair.generate();
+  const token = Keypair.generate().publicKey;
+
+  const [governancePda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("governance")],
+    governance.programId
+  );
+  const [governanceProgramData] = PublicKey.findProgramAddressSync(
+    [governance.programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+  );
+  const [priceFeedPda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("price_feed")],
+    priceFeed.programId
+  );
+  const [tokenPricePda] = PublicKey.findProgramAddressSync(
+    [Buffer.from("token_price"), token.toBuffer()],
+    priceFeed.programId
+  );
+
+  async function now(): Promise<BN> {
+    const slot = await provider.connection.getSlot();
+    return new BN(await provider.connection.getBlockTime(slot));
+  }
+
+  async function pushSigned(signer: Keypair, minPriceX96: BN, maxPriceX96: BN) {
+    const prices = [{ token, minPriceX96, maxPriceX96, timestamp: await now() }];
+    const verify = Ed25519Program.createInstructionWithPrivateKey({
+      privateKey: signer.secretKey,
+      message: serializePrices(prices),
+    });
+    return priceFeed.methods
+      .updatePricesSigned(prices)
+      .accounts({ priceFeed: priceFeedPda, instructions: SYSVAR_INSTRUCTIONS_PUBKEY })
+      .remainingAccounts([{ pubkey: tokenPricePda, isSigner: false, isWritable: true }])
+      .preInstructions([verify])
+      .rpc();
+  }
+
+  before(async () => {
+    await governance.methods
+      .initialize(governor)
+      .accounts({
+        governance: governancePda,
+        payer: governor,
+        program: governance.programId,
+        programData: governanceProgramData,
+      })
+      .rpc();
+    await priceFeed.methods
+      .initialize(new BN(60), 500)
+      .accounts({ authorizedAccount: governor, governance: governancePda, priceFeed: priceFeedPda, payer: governor })
+      .rpc();
+    await priceFeed.methods
+      .addUpdater(updater.publicKey)
+      .accounts({ authorizedAccount: governor, governance: governancePda, priceFeed: priceFeedPda })
+      .rpc();
+    await priceFeed.methods
+      .addToken(token)
+      .accounts({ authorizedAccount: governor, governance: governancePda, tokenPrice: tokenPricePda, payer: governor })
+      .rpc();
+  });
+
+  it("accepts prices signed by an updater", async () => {
+    await pushSigned(updater, new BN(1_000), new BN(1_010));
+    const price = await priceFeed.account.tokenPrice.fetch(tokenPricePda);
+    assert.equal(price.minPriceX96.toNumber(), 1_000);
+    assert.equal(price.maxPriceX96.toNumber(), 1_010);
+  });
+
+  it("rejects prices signed by another key", async () => {
+    try {
+      await pushSigned(Keypair.generate(), new BN(1_001), new BN(1_011));
+      assert.fail("expected the push to be rejected");
+    } catch (e) {
+      assert.include(e.toString(), "CallerUnauthorized");
+    }
+  });
+});